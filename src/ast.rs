@@ -3,7 +3,7 @@ use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 
 use crate::node_id::NodeId;
-use crate::span::Span;
+use crate::span::{Span, Spanned};
 
 #[derive(Clone)]
 pub struct N<T> {
@@ -44,3 +44,9 @@ impl<T: Hash> Hash for N<T> {
         self.data.hash(state)
     }
 }
+
+impl<T> Spanned for N<T> {
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
@@ -1,37 +1,166 @@
-use std::ops::Deref;
+use std::fmt;
+use std::ops::Range;
 use std::sync::Arc;
 
+use crate::source_map::FileId;
 use crate::span::Span;
 
-#[derive(Clone, Debug)]
-pub struct Chars(Arc<Vec<char>>);
+// a byte offset is recorded every `CHECKPOINT` chars, so converting a char offset to a byte
+// offset decodes at most `CHECKPOINT - 1` chars
+const CHECKPOINT: usize = 64;
+
+/// Text stored as utf-8, all offsets and spans count chars.
+#[derive(Clone)]
+pub struct Chars {
+    file: FileId,
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    text: String,
+    len: usize,
+    // byte offset of the char `i * CHECKPOINT`, empty for ascii text
+    checkpoints: Vec<usize>,
+}
 
 impl Chars {
     pub fn new(s: &str) -> Self {
-        let chars: Vec<_> = s.chars().collect();
-        Chars(Arc::new(chars))
+        Self::new_in(FileId::default(), s)
     }
 
-    pub fn get_string(&self, span: Span) -> Option<String> {
-        if span.end() > self.len() {
+    pub fn new_in(file: FileId, s: &str) -> Self {
+        Self::from_string_in(file, s.to_string())
+    }
+
+    pub fn from_string(s: String) -> Self {
+        Self::from_string_in(FileId::default(), s)
+    }
+
+    pub fn from_string_in(file: FileId, text: String) -> Self {
+        let mut len = 0;
+        let mut checkpoints = vec![];
+        if !text.is_ascii() {
+            for (i, (pos, _)) in text.char_indices().enumerate() {
+                if i % CHECKPOINT == 0 {
+                    checkpoints.push(pos);
+                }
+                len += 1;
+            }
+        } else {
+            len = text.len();
+        }
+        let inner = Inner {
+            text,
+            len,
+            checkpoints,
+        };
+        Chars {
+            file,
+            inner: Arc::new(inner),
+        }
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.inner.text
+    }
+
+    /// Number of chars.
+    pub fn len(&self) -> usize {
+        self.inner.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.len == 0
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.inner.text.len()
+    }
+
+    pub fn char_at(&self, offset: usize) -> Option<char> {
+        let pos = self.byte_offset(offset)?;
+        self.as_str()[pos..].chars().next()
+    }
+
+    /// Convert a char offset to a byte offset, `len()` maps to `byte_len()`.
+    pub fn byte_offset(&self, offset: usize) -> Option<usize> {
+        if offset > self.len() {
+            return None;
+        }
+        if self.inner.checkpoints.is_empty() {
+            return Some(offset);
+        }
+        // there is no checkpoint at `len()` when it is a multiple of `CHECKPOINT`
+        if offset == self.len() {
+            return Some(self.byte_len());
+        }
+        let pos = self.inner.checkpoints[offset / CHECKPOINT];
+        let rest = offset % CHECKPOINT;
+        let ret = match self.as_str()[pos..].char_indices().nth(rest) {
+            Some((d, _)) => pos + d,
+            None => self.byte_len(),
+        };
+        Some(ret)
+    }
+
+    /// Convert a byte offset to a char offset, `None` if it is not on a char boundary.
+    pub fn char_offset(&self, pos: usize) -> Option<usize> {
+        if !self.as_str().is_char_boundary(pos) {
+            return None;
+        }
+        if self.inner.checkpoints.is_empty() {
+            return Some(pos);
+        }
+        let i = self.inner.checkpoints.partition_point(|d| *d <= pos) - 1;
+        let start = self.inner.checkpoints[i];
+        let rest = self.as_str()[start..pos].chars().count();
+        Some(i * CHECKPOINT + rest)
+    }
+
+    pub fn iter(&self) -> std::str::Chars<'_> {
+        self.as_str().chars()
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Option<&str> {
+        if range.start > range.end {
+            return None;
+        }
+        let start = self.byte_offset(range.start)?;
+        let end = self.byte_offset(range.end)?;
+        Some(&self.as_str()[start..end])
+    }
+
+    pub fn get_str(&self, span: Span) -> Option<&str> {
+        if span.file() != self.file || span.end() > self.len() {
             None
         } else {
-            let s = &self.0[span.start()..span.end()];
-            Some(s.iter().collect())
+            let start = self.byte_offset(span.start())?;
+            let end = self.byte_offset(span.end())?;
+            Some(&self.as_str()[start..end])
         }
     }
+
+    pub fn get_string(&self, span: Span) -> Option<String> {
+        self.get_str(span).map(|s| s.to_string())
+    }
 }
 
-impl Deref for Chars {
-    type Target = [char];
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl fmt::Debug for Chars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chars")
+            .field("file", &self.file)
+            .field("text", &self.as_str())
+            .finish()
     }
 }
 
 impl PartialEq<Chars> for Chars {
     fn eq(&self, other: &Chars) -> bool {
-        self.0 == other.0
+        self.as_str() == other.as_str()
     }
 }
 
@@ -45,9 +174,7 @@ impl PartialEq<String> for Chars {
 
 impl PartialEq<str> for Chars {
     fn eq(&self, other: &str) -> bool {
-        // TODO: avoid allocation
-        let other = Chars::new(other);
-        self == &other
+        self.as_str() == other
     }
 }
 
@@ -77,5 +204,60 @@ mod tests {
         assert_eq!(a.get_string(Span::new(0, 2)).unwrap(), "a+");
         assert_eq!(a.get_string(Span::new(0, 3)), None);
         assert_eq!(a.get_string(Span::new(2, 3)), None);
+
+        let b = Chars::new_in(FileId(1), "a+");
+        assert_eq!(b.get_string(Span::new_in(FileId(1), 0, 1)).unwrap(), "a");
+        assert_eq!(b.get_string(Span::new(0, 1)), None);
+    }
+
+    #[test]
+    fn test_get_str() {
+        let a = Chars::new("é+😀b");
+        assert_eq!(a.len(), 4);
+        assert_eq!(a.byte_len(), 8);
+        assert_eq!(a.get_str(Span::new(0, 1)).unwrap(), "é");
+        assert_eq!(a.get_str(Span::new(1, 3)).unwrap(), "+😀");
+        assert_eq!(a.get_str(Span::new(3, 4)).unwrap(), "b");
+        assert_eq!(a.get_str(Span::new(3, 5)), None);
+    }
+
+    #[test]
+    fn test_slice() {
+        let a = Chars::new_in(FileId(1), "é+😀b");
+        assert_eq!(a.slice(1..3), Some("+😀"));
+        assert_eq!(a.slice(4..4), Some(""));
+        assert_eq!(a.slice(3..5), None);
+        assert_eq!(a.iter().nth(2), Some('😀'));
+        assert_eq!(a.iter().count(), a.len());
+    }
+
+    #[test]
+    fn test_offset() {
+        let s: String = "aé😀".repeat(50);
+        let a = Chars::new(&s);
+        assert_eq!(a.len(), 150);
+        for (i, (pos, c)) in s.char_indices().enumerate() {
+            assert_eq!(a.byte_offset(i), Some(pos));
+            assert_eq!(a.char_offset(pos), Some(i));
+            assert_eq!(a.char_at(i), Some(c));
+        }
+        assert_eq!(a.byte_offset(150), Some(s.len()));
+        assert_eq!(a.char_offset(s.len()), Some(150));
+        assert_eq!(a.byte_offset(151), None);
+        assert_eq!(a.char_offset(2), None);
+        assert_eq!(a.char_at(150), None);
+
+        // the end of a text with a multiple of `CHECKPOINT` chars
+        let s = "é".repeat(CHECKPOINT);
+        let c = Chars::new(&s);
+        assert_eq!(c.byte_offset(CHECKPOINT), Some(s.len()));
+        assert_eq!(c.get_str(Span::new(0, CHECKPOINT)), Some(s.as_str()));
+        assert_eq!(c.get_str(Span::new(CHECKPOINT, CHECKPOINT)), Some(""));
+        assert_eq!(c.char_at(CHECKPOINT), None);
+
+        let b = Chars::new("ab");
+        assert_eq!(b.byte_offset(1), Some(1));
+        assert_eq!(b.char_offset(2), Some(2));
+        assert_eq!(b.char_offset(3), None);
     }
 }
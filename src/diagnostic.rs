@@ -0,0 +1,284 @@
+use std::fmt;
+
+use crate::span::{Span, S};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified,
+}
+
+impl fmt::Display for Applicability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Applicability::MachineApplicable => "MachineApplicable",
+            Applicability::MaybeIncorrect => "MaybeIncorrect",
+            Applicability::HasPlaceholders => "HasPlaceholders",
+            Applicability::Unspecified => "Unspecified",
+        };
+        f.write_str(s)
+    }
+}
+
+/// An empty span inserts.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn new(span: Span, replacement: impl Into<String>) -> Self {
+        Edit {
+            span,
+            replacement: replacement.into(),
+        }
+    }
+}
+
+/// Its edits are applied together or not at all.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<Edit>,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    pub fn new(message: impl Into<String>, applicability: Applicability) -> Self {
+        Suggestion {
+            message: message.into(),
+            edits: vec![],
+            applicability,
+        }
+    }
+
+    pub fn with_edit(mut self, span: Span, replacement: impl Into<String>) -> Self {
+        self.edits.push(Edit::new(span, replacement));
+        self
+    }
+
+    pub fn is_machine_applicable(&self) -> bool {
+        self.applicability == Applicability::MachineApplicable
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub code: Option<String>,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            code: None,
+            primary: None,
+            secondary: vec![],
+            notes: vec![],
+            help: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self {
+        Self::new(Severity::Note, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.primary = Some(Label::new(span, message));
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label::new(span, message));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    pub fn with_replacement(
+        self,
+        span: Span,
+        message: impl Into<String>,
+        replacement: impl Into<String>,
+        applicability: Applicability,
+    ) -> Self {
+        let suggestion = Suggestion::new(message, applicability).with_edit(span, replacement);
+        self.with_suggestion(suggestion)
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.primary.as_ref().map(|l| l.span)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// `eof` is the span when nothing was found.
+    pub fn expected<T: fmt::Display>(expected: &[T], found: Option<&S<T>>, eof: Span) -> Self {
+        let expected = expected_list(expected);
+        let (found, span) = match found {
+            Some(d) => (d.tok.to_string(), d.span),
+            None => ("end of file".to_string(), eof),
+        };
+        let message = format!("expected {}, found {}", expected, found);
+        Diagnostic::error(message).with_primary(span, format!("expected {}", expected))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity, code, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+// "a", "a or b", "a, b or c"
+pub(crate) fn expected_list<T: fmt::Display>(expected: &[T]) -> String {
+    let names: Vec<_> = expected.iter().map(|t| t.to_string()).collect();
+    match names.split_last() {
+        None => "nothing".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let d = Diagnostic::error("mismatched types")
+            .with_code("E0308")
+            .with_primary(Span::new(4, 6), "expected `i32`")
+            .with_label(Span::new(0, 2), "declared here")
+            .with_note("note")
+            .with_help("help");
+        assert!(d.is_error());
+        assert_eq!(d.span(), Some(Span::new(4, 6)));
+        assert_eq!(
+            d.secondary,
+            vec![Label::new(Span::new(0, 2), "declared here")]
+        );
+        assert_eq!(d.notes, vec!["note".to_string()]);
+        assert_eq!(d.help, vec!["help".to_string()]);
+        assert_eq!(d.to_string(), "error[E0308]: mismatched types");
+        assert_eq!(Diagnostic::warning("w").to_string(), "warning: w");
+        assert_eq!(Diagnostic::note("n").span(), None);
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let d = Diagnostic::error("expected `;`").with_replacement(
+            Span::point(3),
+            "add a semicolon",
+            ";",
+            Applicability::MachineApplicable,
+        );
+        let s = &d.suggestions[0];
+        assert!(s.is_machine_applicable());
+        assert_eq!(s.edits, vec![Edit::new(Span::point(3), ";")]);
+
+        let s = Suggestion::new("swap", Applicability::MaybeIncorrect)
+            .with_edit(Span::new(0, 1), "b")
+            .with_edit(Span::new(2, 3), "a");
+        assert_eq!(s.edits.len(), 2);
+        assert!(!s.is_machine_applicable());
+        assert_eq!(s.applicability.to_string(), "MaybeIncorrect");
+    }
+
+    #[test]
+    fn test_expected() {
+        let eof = Span::point(9);
+        let found = S {
+            span: Span::new(2, 3),
+            tok: "`+`",
+        };
+        let d = Diagnostic::expected(&["`;`"], Some(&found), eof);
+        assert_eq!(d.message, "expected `;`, found `+`");
+        assert_eq!(d.primary, Some(Label::new(Span::new(2, 3), "expected `;`")));
+
+        let d = Diagnostic::expected(&["`)`", "`,`"], None, eof);
+        assert_eq!(d.message, "expected `)` or `,`, found end of file");
+        assert_eq!(d.span(), Some(eof));
+
+        let d = Diagnostic::expected(&["`)`", "`,`", "identifier"], Some(&found), eof);
+        assert_eq!(d.message, "expected `)`, `,` or identifier, found `+`");
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::span::Span;
+
+/// The root id marks user written source.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Default)]
+pub struct ExpnId(pub(crate) usize);
+
+impl ExpnId {
+    pub fn root() -> Self {
+        ExpnId(0)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExpnKind {
+    Macro(String),
+    Desugaring(String),
+}
+
+impl ExpnKind {
+    pub fn name(&self) -> &str {
+        match self {
+            ExpnKind::Macro(name) | ExpnKind::Desugaring(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for ExpnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpnKind::Macro(name) => write!(f, "expansion of macro `{}`", name),
+            ExpnKind::Desugaring(name) => write!(f, "desugaring of `{}`", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExpnData {
+    pub kind: ExpnKind,
+    pub call_site: Span,
+    pub def_site: Option<Span>,
+}
+
+impl ExpnData {
+    pub fn new(kind: ExpnKind, call_site: Span) -> Self {
+        ExpnData {
+            kind,
+            call_site,
+            def_site: None,
+        }
+    }
+
+    pub fn with_def_site(mut self, def_site: Span) -> Self {
+        self.def_site = Some(def_site);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExpnTable {
+    // `ExpnId(i + 1)` is stored at index `i`
+    data: Vec<ExpnData>,
+}
+
+impl ExpnTable {
+    pub fn new() -> Self {
+        ExpnTable { data: vec![] }
+    }
+
+    pub fn register(&mut self, data: ExpnData) -> ExpnId {
+        let id = ExpnId(self.data.len() + 1);
+        // call sites always refer to earlier expansions, so the chain can not form a cycle
+        assert!(data.call_site.expn() < id, "unknown call site expansion");
+        self.data.push(data);
+        id
+    }
+
+    pub fn get(&self, id: ExpnId) -> Option<&ExpnData> {
+        if id.is_root() {
+            None
+        } else {
+            self.data.get(id.0 - 1)
+        }
+    }
+
+    /// Innermost first.
+    pub fn backtrace(&self, span: Span) -> Vec<&ExpnData> {
+        let mut ret = vec![];
+        let mut expn = span.expn();
+        while let Some(data) = self.get(expn) {
+            ret.push(data);
+            expn = data.call_site.expn();
+        }
+        ret
+    }
+
+    /// The outermost call site.
+    pub fn source_span(&self, span: Span) -> Span {
+        match self.backtrace(span).last() {
+            Some(data) => data.call_site,
+            None => span,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root() {
+        let table = ExpnTable::new();
+        let span = Span::new(0, 1);
+        assert!(span.expn().is_root());
+        assert!(table.get(ExpnId::root()).is_none());
+        assert!(table.backtrace(span).is_empty());
+        assert_eq!(table.source_span(span), span);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut table = ExpnTable::new();
+        let call = Span::new(3, 8);
+        let outer = table.register(ExpnData::new(ExpnKind::Macro("vec".into()), call));
+
+        let inner_call = Span::new(20, 25).with_expn(outer);
+        let data = ExpnData::new(ExpnKind::Desugaring("for".into()), inner_call)
+            .with_def_site(Span::new(40, 50));
+        let inner = table.register(data);
+
+        let generated = Span::new(20, 22).with_expn(inner);
+        let trace = table.backtrace(generated);
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].kind, ExpnKind::Desugaring("for".into()));
+        assert_eq!(trace[0].def_site, Some(Span::new(40, 50)));
+        assert_eq!(trace[1].call_site, call);
+        assert_eq!(table.source_span(generated), call);
+        assert_eq!(trace[1].kind.to_string(), "expansion of macro `vec`");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_panic() {
+        let mut table = ExpnTable::new();
+        let call = Span::new(0, 1).with_expn(ExpnId(1));
+        table.register(ExpnData::new(ExpnKind::Macro("m".into()), call));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::chars::Chars;
+use crate::diagnostic::{Diagnostic, Edit, Suggestion};
+use crate::source_map::{FileId, SourceMap};
+use crate::span::Span;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Conflict {
+    /// `with == index` when the edits of one suggestion overlap each other.
+    Overlap {
+        index: usize,
+        with: usize,
+        span: Span,
+    },
+    OutOfBounds {
+        index: usize,
+        span: Span,
+    },
+    OtherFile {
+        index: usize,
+        span: Span,
+    },
+}
+
+impl Conflict {
+    pub fn index(&self) -> usize {
+        match self {
+            Conflict::Overlap { index, .. }
+            | Conflict::OutOfBounds { index, .. }
+            | Conflict::OtherFile { index, .. } => *index,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fixed {
+    pub source: String,
+    pub applied: Vec<usize>,
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FixedFiles {
+    /// Only the files with applied edits.
+    pub sources: Vec<(FileId, String)>,
+    pub applied: Vec<usize>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// First come first served, a suggestion overlapping an accepted one or editing another file is
+/// a conflict.
+pub fn apply<'a>(chars: &Chars, suggestions: impl IntoIterator<Item = &'a Suggestion>) -> Fixed {
+    let fixed = fix(|file| (file == chars.file()).then_some(chars), suggestions);
+    let source = match fixed.sources.into_iter().next() {
+        Some((_, source)) => source,
+        None => chars.as_str().to_string(),
+    };
+    Fixed {
+        source,
+        applied: fixed.applied,
+        conflicts: fixed.conflicts,
+    }
+}
+
+/// A suggestion is applied to all of its files or to none.
+pub fn apply_in<'a>(
+    source_map: &SourceMap,
+    suggestions: impl IntoIterator<Item = &'a Suggestion>,
+) -> FixedFiles {
+    fix(|file| source_map.chars(file), suggestions)
+}
+
+/// The indices in `Fixed` count the machine applicable suggestions only.
+pub fn apply_diagnostics<'a>(
+    chars: &Chars,
+    diags: impl IntoIterator<Item = &'a Diagnostic>,
+) -> Fixed {
+    apply(chars, machine_applicable(diags))
+}
+
+pub fn apply_diagnostics_in<'a>(
+    source_map: &SourceMap,
+    diags: impl IntoIterator<Item = &'a Diagnostic>,
+) -> FixedFiles {
+    apply_in(source_map, machine_applicable(diags))
+}
+
+fn machine_applicable<'a>(
+    diags: impl IntoIterator<Item = &'a Diagnostic>,
+) -> impl Iterator<Item = &'a Suggestion> {
+    let suggestions = diags.into_iter().flat_map(|d| d.suggestions.iter());
+    suggestions.filter(|s| s.is_machine_applicable())
+}
+
+fn fix<'a, 'c>(
+    files: impl Fn(FileId) -> Option<&'c Chars>,
+    suggestions: impl IntoIterator<Item = &'a Suggestion>,
+) -> FixedFiles {
+    let mut accepted: Vec<(&Edit, usize)> = vec![];
+    let mut applied = vec![];
+    let mut conflicts = vec![];
+    for (index, suggestion) in suggestions.into_iter().enumerate() {
+        match check(&files, suggestion, index, &accepted) {
+            Some(conflict) => conflicts.push(conflict),
+            None => {
+                accepted.extend(suggestion.edits.iter().map(|e| (e, index)));
+                applied.push(index);
+            }
+        }
+    }
+
+    accepted.sort_by_key(|(e, _)| (e.span.file(), e.span.start(), e.span.end()));
+    let mut sources = vec![];
+    for group in accepted.chunk_by(|(a, _), (b, _)| a.span.file() == b.span.file()) {
+        let file = group[0].0.span.file();
+        // files were checked, so they exist
+        let chars = files(file).unwrap();
+        sources.push((file, render(chars, group.iter().map(|(e, _)| *e))));
+    }
+    FixedFiles {
+        sources,
+        applied,
+        conflicts,
+    }
+}
+
+// `edits` are sorted and in bounds
+fn render<'a>(chars: &Chars, edits: impl Iterator<Item = &'a Edit>) -> String {
+    let text = chars.as_str();
+    let mut source = String::with_capacity(text.len());
+    let mut pos = 0;
+    for edit in edits {
+        let start = chars.byte_offset(edit.span.start()).unwrap();
+        source.push_str(&text[pos..start]);
+        source.push_str(&edit.replacement);
+        pos = chars.byte_offset(edit.span.end()).unwrap();
+    }
+    source.push_str(&text[pos..]);
+    source
+}
+
+fn check<'c>(
+    files: &impl Fn(FileId) -> Option<&'c Chars>,
+    suggestion: &Suggestion,
+    index: usize,
+    accepted: &[(&Edit, usize)],
+) -> Option<Conflict> {
+    let edits = &suggestion.edits;
+    for (i, edit) in edits.iter().enumerate() {
+        let span = edit.span;
+        let chars = match files(span.file()) {
+            Some(chars) => chars,
+            None => return Some(Conflict::OtherFile { index, span }),
+        };
+        if span.end() > chars.len() {
+            return Some(Conflict::OutOfBounds { index, span });
+        }
+        let own = edits[..i].iter().map(|e| (e, index));
+        for (other, with) in accepted.iter().copied().chain(own) {
+            if overlaps(span, other.span) {
+                return Some(Conflict::Overlap { index, with, span });
+            }
+        }
+    }
+    None
+}
+
+// Edits may touch, but two insertions at one point have no order.
+fn overlaps(a: Span, b: Span) -> bool {
+    if a.file() != b.file() {
+        false
+    } else if a.is_empty() && b.is_empty() {
+        a.start() == b.start()
+    } else {
+        a.start() < b.end() && b.start() < a.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Applicability;
+
+    fn replace(span: Span, text: &str) -> Suggestion {
+        Suggestion::new("", Applicability::MachineApplicable).with_edit(span, text)
+    }
+
+    #[test]
+    fn test_apply() {
+        let chars = Chars::new("lét a = 1");
+        let suggestions = [
+            replace(Span::point(9), ";"),
+            replace(Span::new(0, 3), "let"),
+            replace(Span::new(3, 3), " mut"),
+        ];
+        let fixed = apply(&chars, &suggestions);
+        assert_eq!(fixed.source, "let mut a = 1;");
+        assert_eq!(fixed.applied, vec![0, 1, 2]);
+        assert!(fixed.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_apply_at_end() {
+        let text = "é".repeat(64);
+        let fixed = apply(&Chars::new(&text), &[replace(Span::point(64), ";")]);
+        assert_eq!(fixed.source, text + ";");
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let chars = Chars::new("a + b");
+        let suggestions = [
+            replace(Span::new(0, 3), "c"),
+            replace(Span::new(2, 5), "d"),
+            replace(Span::point(5), ";"),
+            replace(Span::point(5), "!"),
+            replace(Span::new(4, 9), "e"),
+            Suggestion::new("", Applicability::MachineApplicable)
+                .with_edit(Span::new(4, 5), "x")
+                .with_edit(Span::new(4, 5), "y"),
+        ];
+        let fixed = apply(&chars, &suggestions);
+        assert_eq!(fixed.source, "c b;");
+        assert_eq!(fixed.applied, vec![0, 2]);
+        assert_eq!(
+            fixed.conflicts,
+            vec![
+                Conflict::Overlap {
+                    index: 1,
+                    with: 0,
+                    span: Span::new(2, 5)
+                },
+                Conflict::Overlap {
+                    index: 3,
+                    with: 2,
+                    span: Span::point(5)
+                },
+                Conflict::OutOfBounds {
+                    index: 4,
+                    span: Span::new(4, 9)
+                },
+                Conflict::Overlap {
+                    index: 5,
+                    with: 5,
+                    span: Span::new(4, 5)
+                },
+            ]
+        );
+        assert_eq!(fixed.conflicts[2].index(), 4);
+    }
+
+    #[test]
+    fn test_apply_diagnostics() {
+        let chars = Chars::new_in(FileId(1), "f(a b)");
+        let other = Span::new_in(FileId(2), 0, 1);
+        let d1 = Diagnostic::error("expected `,`")
+            .with_replacement(
+                Span::new_in(FileId(1), 3, 3),
+                "add a comma",
+                ",",
+                Applicability::MachineApplicable,
+            )
+            .with_replacement(
+                Span::new_in(FileId(1), 4, 5),
+                "remove the argument",
+                "",
+                Applicability::MaybeIncorrect,
+            );
+        let d2 = Diagnostic::error("e").with_suggestion(
+            Suggestion::new("", Applicability::MachineApplicable)
+                .with_edit(other, "x")
+                .with_edit(Span::new_in(FileId(1), 0, 1), "g"),
+        );
+        let fixed = apply_diagnostics(&chars, vec![&d1, &d2]);
+        assert_eq!(fixed.source, "f(a, b)");
+        assert_eq!(fixed.applied, vec![0]);
+        assert_eq!(
+            fixed.conflicts,
+            vec![Conflict::OtherFile {
+                index: 1,
+                span: other
+            }]
+        );
+    }
+
+    #[test]
+    fn test_apply_in() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.x", "f(a b)");
+        let b = sm.add_file("b.x", "fn f(a)");
+        let both = |a_span, b_span| {
+            Suggestion::new("", Applicability::MachineApplicable)
+                .with_edit(a_span, "")
+                .with_edit(b_span, ", b")
+        };
+        let suggestions = [
+            replace(Span::new_in(a, 3, 3), ","),
+            // overlaps in `a`, so not applied in `b` either
+            both(Span::new_in(a, 2, 4), Span::new_in(b, 6, 6)),
+            both(Span::new_in(a, 3, 5), Span::new_in(b, 6, 6)),
+            replace(Span::new_in(FileId(7), 0, 0), "x"),
+        ];
+        let fixed = apply_in(&sm, &suggestions);
+        assert_eq!(
+            fixed.sources,
+            vec![(a, "f(a,)".to_string()), (b, "fn f(a, b)".to_string())]
+        );
+        assert_eq!(fixed.applied, vec![0, 2]);
+        assert_eq!(
+            fixed
+                .conflicts
+                .iter()
+                .map(|c| c.index())
+                .collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt::{self, Write};
+
+use crate::diagnostic::{Diagnostic, Label, Severity, Suggestion};
+use crate::registry::Registry;
+use crate::render::Renderer;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Num(usize),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub(crate) fn str(s: impl Into<String>) -> Json {
+        Json::Str(s.into())
+    }
+
+    pub(crate) fn opt<T>(v: Option<T>, f: impl FnOnce(T) -> Json) -> Json {
+        v.map(f).unwrap_or(Json::Null)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Num(n) => write!(f, "{}", n),
+            Json::Str(s) => write_str(f, s),
+            Json::Arr(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Obj(fields) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// One object per line, in the shape of `rustc --error-format=json`.
+#[derive(Clone, Debug)]
+pub struct JsonEmitter {
+    rendered: bool,
+    registry: Option<&'static Registry>,
+}
+
+impl Default for JsonEmitter {
+    fn default() -> Self {
+        JsonEmitter {
+            rendered: true,
+            registry: None,
+        }
+    }
+}
+
+impl JsonEmitter {
+    pub fn new() -> Self {
+        JsonEmitter::default()
+    }
+
+    pub fn rendered(mut self, rendered: bool) -> Self {
+        self.rendered = rendered;
+        self
+    }
+
+    pub fn registry(mut self, registry: &'static Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn emit(&self, diag: &Diagnostic, source_map: &SourceMap) -> String {
+        self.diagnostic(diag, source_map).to_string()
+    }
+
+    pub fn emit_all<'a>(
+        &self,
+        diags: impl IntoIterator<Item = &'a Diagnostic>,
+        source_map: &SourceMap,
+    ) -> String {
+        let mut ret = String::new();
+        for diag in diags {
+            ret.push_str(&self.emit(diag, source_map));
+            ret.push('\n');
+        }
+        ret
+    }
+
+    fn diagnostic(&self, diag: &Diagnostic, source_map: &SourceMap) -> Json {
+        let primary = diag.primary.iter().map(|l| (l, true));
+        let labels = primary.chain(diag.secondary.iter().map(|l| (l, false)));
+        let spans = labels
+            .filter_map(|(l, primary)| label_json(l, primary, source_map))
+            .collect();
+
+        let notes = diag.notes.iter().map(|n| (Severity::Note, n));
+        let help = diag.help.iter().map(|h| (Severity::Help, h));
+        let children = notes
+            .chain(help)
+            .map(|(severity, message)| child_json(severity, message, vec![]));
+        let suggestions = diag
+            .suggestions
+            .iter()
+            .map(|s| suggestion_json(s, source_map));
+
+        let rendered = if self.rendered {
+            Json::str(Renderer::new().render(diag, source_map))
+        } else {
+            Json::Null
+        };
+        Json::Obj(vec![
+            ("$message_type", Json::str("diagnostic")),
+            ("message", Json::str(diag.message.clone())),
+            ("code", self.code_json(diag.code.as_deref())),
+            ("level", Json::str(diag.severity.to_string())),
+            ("spans", Json::Arr(spans)),
+            ("children", Json::Arr(children.chain(suggestions).collect())),
+            ("rendered", rendered),
+        ])
+    }
+
+    fn code_json(&self, code: Option<&str>) -> Json {
+        Json::opt(code, |code| {
+            let explanation = self.registry.and_then(|r| r.explain(code));
+            Json::Obj(vec![
+                ("code", Json::str(code)),
+                ("explanation", Json::opt(explanation, Json::str)),
+            ])
+        })
+    }
+}
+
+fn child_json(severity: Severity, message: &str, spans: Vec<Json>) -> Json {
+    Json::Obj(vec![
+        ("message", Json::str(message)),
+        ("code", Json::Null),
+        ("level", Json::str(severity.to_string())),
+        ("spans", Json::Arr(spans)),
+        ("children", Json::Arr(vec![])),
+        ("rendered", Json::Null),
+    ])
+}
+
+// a help child with one span per edit, like rustc
+fn suggestion_json(suggestion: &Suggestion, source_map: &SourceMap) -> Json {
+    let spans = suggestion.edits.iter().filter_map(|edit| {
+        let mut fields = span_fields(edit.span, source_map)?;
+        fields.push(("is_primary", Json::Bool(true)));
+        fields.push(("label", Json::Null));
+        fields.push(("suggested_replacement", Json::str(edit.replacement.clone())));
+        let applicability = suggestion.applicability.to_string();
+        fields.push(("suggestion_applicability", Json::str(applicability)));
+        Some(Json::Obj(fields))
+    });
+    child_json(Severity::Help, &suggestion.message, spans.collect())
+}
+
+fn label_json(label: &Label, primary: bool, source_map: &SourceMap) -> Option<Json> {
+    let mut fields = span_fields(label.span, source_map)?;
+    let message = Some(label.message.as_str()).filter(|m| !m.is_empty());
+    fields.push(("is_primary", Json::Bool(primary)));
+    fields.push(("label", Json::opt(message, Json::str)));
+    fields.push(("suggested_replacement", Json::Null));
+    fields.push(("suggestion_applicability", Json::Null));
+    Some(Json::Obj(fields))
+}
+
+fn span_fields(span: Span, source_map: &SourceMap) -> Option<Vec<(&'static str, Json)>> {
+    let file = source_map.get(span.file())?;
+    let bytes = file.original_range(span)?;
+    let (start, end) = file.lines().lookup_span(span)?;
+    let expansion = source_map.expansions().get(span.expn()).map(|data| {
+        let call_site = span_fields(data.call_site, source_map).map(Json::Obj);
+        let def_site = data.def_site.and_then(|s| span_fields(s, source_map));
+        Json::Obj(vec![
+            ("span", call_site.unwrap_or(Json::Null)),
+            ("macro_decl_name", Json::str(data.kind.name())),
+            ("def_site_span", Json::opt(def_site, Json::Obj)),
+        ])
+    });
+    Some(vec![
+        ("file_name", Json::str(file.name())),
+        ("byte_start", Json::Num(bytes.start)),
+        ("byte_end", Json::Num(bytes.end)),
+        ("char_start", Json::Num(span.start())),
+        ("char_end", Json::Num(span.end())),
+        ("line_start", Json::Num(start.line + 1)),
+        ("line_end", Json::Num(end.line + 1)),
+        ("column_start", Json::Num(start.col + 1)),
+        ("column_end", Json::Num(end.col + 1)),
+        ("expansion", expansion.unwrap_or(Json::Null)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Applicability;
+    use crate::expn::{ExpnData, ExpnKind};
+
+    #[test]
+    fn test_json_value() {
+        let v = Json::Obj(vec![
+            (
+                "a",
+                Json::Arr(vec![Json::Num(1), Json::Bool(false), Json::Null]),
+            ),
+            ("b", Json::str("q\"\\\n\u{1}é")),
+        ]);
+        assert_eq!(
+            v.to_string(),
+            r#"{"a":[1,false,null],"b":"q\"\\\n\u0001é"}"#
+        );
+    }
+
+    #[test]
+    fn test_emit() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("main.x", "é = 1 +;");
+        let d = Diagnostic::error("expected expression")
+            .with_code("E0001")
+            .with_primary(Span::new_in(a, 7, 8), "here")
+            .with_label(Span::new_in(a, 0, 1), "")
+            .with_note("n");
+        let expected = concat!(
+            r#"{"$message_type":"diagnostic","message":"expected expression","#,
+            r#""code":{"code":"E0001","explanation":null},"level":"error","spans":["#,
+            r#"{"file_name":"main.x","byte_start":8,"byte_end":9,"char_start":7,"char_end":8,"#,
+            r#""line_start":1,"line_end":1,"column_start":8,"column_end":9,"expansion":null,"#,
+            r#""is_primary":true,"label":"here","suggested_replacement":null,"#,
+            r#""suggestion_applicability":null},"#,
+            r#"{"file_name":"main.x","byte_start":0,"byte_end":2,"char_start":0,"char_end":1,"#,
+            r#""line_start":1,"line_end":1,"column_start":1,"column_end":2,"expansion":null,"#,
+            r#""is_primary":false,"label":null,"suggested_replacement":null,"#,
+            r#""suggestion_applicability":null}],"#,
+            r#""children":[{"message":"n","code":null,"level":"note","spans":[],"children":[],"#,
+            r#""rendered":null}],"rendered":null}"#,
+        );
+        let emitter = JsonEmitter::new().rendered(false);
+        assert_eq!(emitter.emit(&d, &sm), expected);
+
+        let out = JsonEmitter::new().emit_all(vec![&d, &d], &sm);
+        assert_eq!(out.lines().count(), 2);
+        assert!(out.contains(r#""rendered":"error[E0001]: expected expression\n --> main.x:1:8"#));
+    }
+
+    #[test]
+    fn test_emit_explanation() {
+        crate::error_codes! {
+            static REGISTRY;
+            E0001: "An expression was expected.\n",
+        }
+        let sm = SourceMap::new();
+        let d = Diagnostic::error("e").with_code(E0001);
+        let out = JsonEmitter::new().registry(&REGISTRY).emit(&d, &sm);
+        let expected = r#""code":{"code":"E0001","explanation":"An expression was expected.\n"}"#;
+        assert!(out.contains(expected));
+    }
+
+    #[test]
+    fn test_emit_suggestion() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("main.x", "f(a b)");
+        let d = Diagnostic::error("expected `,`").with_replacement(
+            Span::new_in(a, 3, 3),
+            "add a comma",
+            ",",
+            Applicability::MachineApplicable,
+        );
+        let out = JsonEmitter::new().rendered(false).emit(&d, &sm);
+        let expected = concat!(
+            r#""children":[{"message":"add a comma","code":null,"level":"help","spans":["#,
+            r#"{"file_name":"main.x","byte_start":3,"byte_end":3,"char_start":3,"char_end":3,"#,
+            r#""line_start":1,"line_end":1,"column_start":4,"column_end":4,"expansion":null,"#,
+            r#""is_primary":true,"label":null,"suggested_replacement":",","#,
+            r#""suggestion_applicability":"MachineApplicable"}],"children":[],"rendered":null}]"#,
+        );
+        assert!(out.contains(expected));
+    }
+
+    #[test]
+    fn test_emit_expansion() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("main.x", "m!(x)");
+        let data = ExpnData::new(ExpnKind::Macro("m".into()), Span::new_in(a, 0, 5));
+        let expn = sm.register_expansion(data);
+        let d = Diagnostic::error("e").with_primary(Span::new_in(a, 3, 4).with_expn(expn), "");
+        let out = JsonEmitter::new().rendered(false).emit(&d, &sm);
+        let expected = concat!(
+            r#""expansion":{"span":{"file_name":"main.x","byte_start":0,"byte_end":5,"#,
+            r#""char_start":0,"char_end":5,"line_start":1,"line_end":1,"column_start":1,"#,
+            r#""column_end":6,"expansion":null},"macro_decl_name":"m","#,
+            r#""def_site_span":null}"#,
+        );
+        assert!(out.contains(expected));
+    }
+}
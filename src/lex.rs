@@ -1,24 +1,79 @@
+use std::fmt;
+
 use crate::chars::Chars;
-use crate::span::{Span, S};
+use crate::diagnostic::Diagnostic;
+use crate::sink::{DiagnosticSink, ErrorLimitReached};
+use crate::source_map::SourceMap;
+use crate::span::{Span, Spanned, S};
 
 #[derive(Debug, Clone)]
 pub struct LexCtx {
     chars: Chars,
+    // char offsets
     cursor: usize,
     start: usize,
+    // byte offsets of `cursor` and `start`
+    pos: usize,
+    start_pos: usize,
+    sink: DiagnosticSink,
 }
 
 impl LexCtx {
     pub fn new(input: &str) -> LexCtx {
         let chars = Chars::new(input);
+        Self::from_chars(chars)
+    }
+
+    pub fn new_in(source_map: &mut SourceMap, name: &str, input: &str) -> LexCtx {
+        let id = source_map.add_file(name, input);
+        let chars = source_map.chars(id).unwrap().clone();
+        Self::from_chars(chars)
+    }
+
+    pub fn from_chars(chars: Chars) -> LexCtx {
         LexCtx {
             chars,
             cursor: 0,
             start: 0,
+            pos: 0,
+            start_pos: 0,
+            sink: DiagnosticSink::new(),
         }
     }
+
+    pub fn with_sink(mut self, sink: DiagnosticSink) -> LexCtx {
+        self.sink = sink;
+        self
+    }
+
+    pub fn sink(&self) -> &DiagnosticSink {
+        &self.sink
+    }
+}
+
+/// `span` covers the lexeme that failed, or is the point it failed at.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LexError<E> {
+    pub span: Span,
+    pub error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for LexError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for LexError<E> {}
+
+impl<E> Spanned for LexError<E> {
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
+pub type Lexed<T, E> = (Vec<S<T>>, Vec<LexError<E>>);
+
 pub trait Lex {
     type Token;
     type Error;
@@ -32,18 +87,25 @@ pub trait Lex {
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // provided
 
-    fn next_s(&mut self) -> Result<Option<S<Self::Token>>, Self::Error> {
-        if let Some(tok) = self.next()? {
-            let span = self.span().unwrap();
-            let tok = S { span, tok };
-            self.ctx_mut().sync();
-            Ok(Some(tok))
-        } else {
-            Ok(None)
+    /// After an error the next call starts right after the failed lexeme.
+    fn next_s(&mut self) -> Result<Option<S<Self::Token>>, LexError<Self::Error>> {
+        match self.next() {
+            Ok(Some(tok)) => {
+                let span = self.span().unwrap();
+                let tok = S { span, tok };
+                self.ctx_mut().sync();
+                Ok(Some(tok))
+            }
+            Ok(None) => Ok(None),
+            Err(error) => {
+                let span = self.span().unwrap_or_else(|| self.point());
+                self.ctx_mut().sync();
+                Err(LexError { span, error })
+            }
         }
     }
 
-    fn tokens(&mut self) -> Result<Vec<S<Self::Token>>, Self::Error> {
+    fn tokens(&mut self) -> Result<Vec<S<Self::Token>>, LexError<Self::Error>> {
         let mut ret = vec![];
         while let Some(tok) = self.next_s()? {
             ret.push(tok);
@@ -51,6 +113,56 @@ pub trait Lex {
         Ok(ret)
     }
 
+    /// Keeps going after errors, the bad text becomes an `unknown` token.
+    fn tokens_recover(&mut self, unknown: Self::Token) -> Lexed<Self::Token, Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        loop {
+            match self.next_s() {
+                Ok(Some(tok)) => tokens.push(tok),
+                Ok(None) => break,
+                Err(e) => {
+                    // skip at least one char, or the same error comes again
+                    if e.span.is_empty() {
+                        self.advance();
+                    }
+                    self.recover();
+                    let span = self.span().map_or(e.span, |s| e.span.merge(s));
+                    if !span.is_empty() {
+                        let tok = unknown.clone();
+                        tokens.push(S { span, tok });
+                    }
+                    errors.push(e);
+                    self.ctx_mut().sync();
+                    if self.eof() {
+                        break;
+                    }
+                }
+            }
+        }
+        (tokens, errors)
+    }
+
+    /// By default skips to where `next` succeeds, probing without emitting.
+    fn recover(&mut self) {
+        // the probes emit into a sink of their own, which is thrown away
+        let sink = std::mem::take(&mut self.ctx_mut().sink);
+        while !self.eof() {
+            let saved = self.ctx().clone();
+            self.ctx_mut().sync();
+            let ok = matches!(self.next(), Ok(Some(_)));
+            *self.ctx_mut() = saved;
+            if ok {
+                break;
+            }
+            self.advance();
+        }
+        self.ctx_mut().sink = sink;
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // provided, delegate to LexCtx
 
@@ -102,47 +214,56 @@ pub trait Lex {
         self.ctx().span()
     }
 
+    fn point(&self) -> Span {
+        self.ctx().point()
+    }
+
     fn get_string(&self) -> Option<String> {
-        let span = self.span()?;
-        self.chars().get_string(span)
+        self.get_str().map(|s| s.to_string())
+    }
+
+    fn get_str(&self) -> Option<&str> {
+        self.ctx().get_str()
     }
 
     fn chars(&self) -> &Chars {
         self.ctx().chars()
     }
+
+    fn emit(&self, diag: Diagnostic) -> Result<(), ErrorLimitReached> {
+        self.ctx().sink().emit(diag)
+    }
 }
 
 impl LexCtx {
+    fn rest(&self) -> &str {
+        &self.chars.as_str()[self.pos..]
+    }
+
     fn eof(&self) -> bool {
-        self.cursor == self.chars.len()
+        self.pos == self.chars.byte_len()
     }
 
     fn peek(&self) -> Option<char> {
-        if self.eof() {
-            None
-        } else {
-            Some(self.chars[self.cursor])
-        }
+        self.rest().chars().next()
     }
 
     fn peek2(&self) -> (Option<char>, Option<char>) {
-        let a = self.peek();
-        let b = if self.cursor < self.chars.len() - 1 {
-            Some(self.chars[self.cursor + 1])
-        } else {
-            None
-        };
+        let mut rest = self.rest().chars();
+        let a = rest.next();
+        let b = rest.next();
         (a, b)
     }
 
     fn advance(&mut self) -> Option<char> {
-        if self.eof() {
-            None
-        } else {
-            let c = self.chars[self.cursor];
-            self.cursor += 1;
-            Some(c)
-        }
+        let c = self.peek()?;
+        self.bump(c);
+        Some(c)
+    }
+
+    fn bump(&mut self, c: char) {
+        self.cursor += 1;
+        self.pos += c.len_utf8();
     }
 
     fn advance_cmp(&mut self, c: char) -> bool {
@@ -192,7 +313,7 @@ impl LexCtx {
     fn advance_if(&mut self, p: impl Fn(char) -> bool) -> bool {
         if let Some(c) = self.peek() {
             if p(c) {
-                self.cursor += 1;
+                self.bump(c);
                 return true;
             }
         }
@@ -205,7 +326,7 @@ impl LexCtx {
             if !p(c) {
                 break;
             }
-            self.cursor += 1;
+            self.bump(c);
             num += 1;
         }
         num
@@ -217,12 +338,25 @@ impl LexCtx {
         if start == end {
             None
         } else {
-            Some(Span::new(start, end))
+            Some(Span::new_in(self.chars.file(), start, end))
+        }
+    }
+
+    fn point(&self) -> Span {
+        Span::point_in(self.chars.file(), self.cursor)
+    }
+
+    fn get_str(&self) -> Option<&str> {
+        if self.start == self.cursor {
+            None
+        } else {
+            Some(&self.chars.as_str()[self.start_pos..self.pos])
         }
     }
 
     fn sync(&mut self) {
-        self.start = self.cursor
+        self.start = self.cursor;
+        self.start_pos = self.pos;
     }
 
     fn chars(&self) -> &Chars {
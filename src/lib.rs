@@ -1,6 +1,21 @@
 pub mod ast;
 pub mod chars;
+pub mod diagnostic;
+pub mod expn;
+pub mod fix;
+pub mod json;
 pub mod lex;
+pub mod line_index;
+pub mod load;
+pub mod memo;
 pub mod node_id;
 pub mod parse;
+pub mod pratt;
+pub mod registry;
+pub mod render;
+pub mod sarif;
+pub mod sink;
+pub mod source_map;
 pub mod span;
+#[cfg(feature = "trace")]
+pub mod trace;
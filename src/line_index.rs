@@ -0,0 +1,202 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::chars::Chars;
+use crate::span::Span;
+
+/// Zero based, the column counts chars. `Display` is one based.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.col + 1)
+    }
+}
+
+// a char which takes more than one byte in utf-8
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct WideChar {
+    offset: usize,
+    len_utf8: usize,
+    len_utf16: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineIndex {
+    // char offset of the first char of every line
+    line_starts: Vec<usize>,
+    wide_chars: Vec<WideChar>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(chars: &Chars) -> Self {
+        let mut line_starts = vec![0];
+        let mut wide_chars = vec![];
+        for (offset, c) in chars.as_str().chars().enumerate() {
+            if c == '\n' {
+                line_starts.push(offset + 1);
+            }
+            if !c.is_ascii() {
+                wide_chars.push(WideChar {
+                    offset,
+                    len_utf8: c.len_utf8(),
+                    len_utf16: c.len_utf16(),
+                });
+            }
+        }
+        LineIndex {
+            line_starts,
+            wide_chars,
+            len: chars.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Without the line terminator.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line)?;
+        let end = match self.line_starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => self.len,
+        };
+        Some(start..end)
+    }
+
+    pub fn line_col(&self, offset: usize) -> Option<LineCol> {
+        if offset > self.len {
+            return None;
+        }
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let col = offset - self.line_starts[line];
+        Some(LineCol { line, col })
+    }
+
+    pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+        let range = self.line_range(line_col.line)?;
+        // the position right after the last char of a line is valid
+        if line_col.col > range.len() {
+            None
+        } else {
+            Some(range.start + line_col.col)
+        }
+    }
+
+    pub fn lookup_span(&self, span: Span) -> Option<(LineCol, LineCol)> {
+        let start = self.line_col(span.start())?;
+        let end = self.line_col(span.end())?;
+        Some((start, end))
+    }
+
+    pub fn utf8_col(&self, offset: usize) -> Option<usize> {
+        let line_col = self.line_col(offset)?;
+        let extra: usize = self
+            .wide_chars_between(offset - line_col.col, offset)
+            .iter()
+            .map(|c| c.len_utf8 - 1)
+            .sum();
+        Some(line_col.col + extra)
+    }
+
+    pub fn utf16_col(&self, offset: usize) -> Option<usize> {
+        let line_col = self.line_col(offset)?;
+        let extra: usize = self
+            .wide_chars_between(offset - line_col.col, offset)
+            .iter()
+            .map(|c| c.len_utf16 - 1)
+            .sum();
+        Some(line_col.col + extra)
+    }
+
+    fn wide_chars_between(&self, start: usize, end: usize) -> &[WideChar] {
+        let lo = self.wide_chars.partition_point(|c| c.offset < start);
+        let hi = self.wide_chars.partition_point(|c| c.offset < end);
+        &self.wide_chars[lo..hi]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lc(line: usize, col: usize) -> LineCol {
+        LineCol { line, col }
+    }
+
+    #[test]
+    fn test_line_col() {
+        let chars = Chars::new("ab\ncd\n\ne");
+        let index = LineIndex::new(&chars);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(0), Some(lc(0, 0)));
+        assert_eq!(index.line_col(2), Some(lc(0, 2)));
+        assert_eq!(index.line_col(3), Some(lc(1, 0)));
+        assert_eq!(index.line_col(5), Some(lc(1, 2)));
+        assert_eq!(index.line_col(6), Some(lc(2, 0)));
+        assert_eq!(index.line_col(7), Some(lc(3, 0)));
+        assert_eq!(index.line_col(8), Some(lc(3, 1)));
+        assert_eq!(index.line_col(9), None);
+    }
+
+    #[test]
+    fn test_offset() {
+        let chars = Chars::new("ab\ncd\n\ne");
+        let index = LineIndex::new(&chars);
+        for offset in 0..=chars.len() {
+            let line_col = index.line_col(offset).unwrap();
+            assert_eq!(index.offset(line_col), Some(offset));
+        }
+        assert_eq!(index.offset(lc(0, 3)), None);
+        assert_eq!(index.offset(lc(2, 1)), None);
+        assert_eq!(index.offset(lc(4, 0)), None);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let chars = Chars::new("ab\ncd\n");
+        let index = LineIndex::new(&chars);
+        assert_eq!(index.line_range(0), Some(0..2));
+        assert_eq!(index.line_range(1), Some(3..5));
+        assert_eq!(index.line_range(2), Some(6..6));
+        assert_eq!(index.line_range(3), None);
+    }
+
+    #[test]
+    fn test_lookup_span() {
+        let chars = Chars::new("ab\ncd");
+        let index = LineIndex::new(&chars);
+        let (start, end) = index.lookup_span(Span::new(1, 4)).unwrap();
+        assert_eq!(start, lc(0, 1));
+        assert_eq!(end, lc(1, 1));
+        assert_eq!(start.to_string(), "1:2");
+        assert_eq!(index.lookup_span(Span::new(1, 6)), None);
+    }
+
+    #[test]
+    fn test_utf_cols() {
+        // 'é' is 2 bytes in utf-8, '😀' is 4 bytes in utf-8 and 2 units in utf-16
+        let chars = Chars::new("é😀a\n😀b");
+        let index = LineIndex::new(&chars);
+        assert_eq!(index.line_col(2), Some(lc(0, 2)));
+        assert_eq!(index.utf8_col(1), Some(2));
+        assert_eq!(index.utf8_col(2), Some(6));
+        assert_eq!(index.utf8_col(3), Some(7));
+        assert_eq!(index.utf16_col(1), Some(1));
+        assert_eq!(index.utf16_col(2), Some(3));
+        assert_eq!(index.utf16_col(3), Some(4));
+        assert_eq!(index.utf8_col(4), Some(0));
+        assert_eq!(index.utf8_col(5), Some(4));
+        assert_eq!(index.utf16_col(5), Some(2));
+        assert_eq!(index.utf16_col(7), None);
+    }
+}
@@ -0,0 +1,349 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+
+use crate::chars::Chars;
+use crate::source_map::FileId;
+use crate::span::Span;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Latin1 => &[],
+        }
+    }
+
+    fn sniff(bytes: &[u8]) -> Encoding {
+        [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+            .iter()
+            .copied()
+            .find(|e| bytes.starts_with(e.bom()))
+            .unwrap_or(Encoding::Utf8)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    encoding: Option<Encoding>,
+    normalize_newlines: bool,
+}
+
+impl LoadOptions {
+    /// Detect the encoding from the byte order mark, fall back to utf-8.
+    pub fn new() -> Self {
+        LoadOptions::default()
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Turn `\r\n` and lone `\r` into `\n`.
+    pub fn normalize_newlines(mut self, normalize: bool) -> Self {
+        self.normalize_newlines = normalize;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    /// `offset` counts bytes.
+    Invalid {
+        encoding: Encoding,
+        offset: usize,
+    },
+    OddLength,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Invalid { encoding, offset } => {
+                write!(f, "invalid {:?} data at byte {}", encoding, offset)
+            }
+            LoadError::OddLength => write!(f, "utf-16 input has an odd number of bytes"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// a run of chars which all take `width` bytes in the original input
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Segment {
+    offset: usize,
+    pos: usize,
+    width: usize,
+}
+
+/// Maps char offsets of the decoded text back to byte offsets of the original input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OffsetMap {
+    segments: Vec<Segment>,
+    len: usize,
+    byte_len: usize,
+}
+
+impl OffsetMap {
+    pub fn original_offset(&self, offset: usize) -> Option<usize> {
+        if offset > self.len {
+            return None;
+        }
+        if offset == self.len {
+            return Some(self.byte_len);
+        }
+        let i = self.segments.partition_point(|s| s.offset <= offset) - 1;
+        let seg = self.segments[i];
+        Some(seg.pos + (offset - seg.offset) * seg.width)
+    }
+
+    pub fn original_range(&self, span: Span) -> Option<Range<usize>> {
+        let start = self.original_offset(span.start())?;
+        let end = self.original_offset(span.end())?;
+        Some(start..end)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Loaded {
+    pub chars: Chars,
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub offsets: OffsetMap,
+}
+
+pub fn load(bytes: &[u8], options: &LoadOptions) -> Result<Loaded, LoadError> {
+    load_in(FileId::default(), bytes, options)
+}
+
+pub fn load_in(file: FileId, bytes: &[u8], options: &LoadOptions) -> Result<Loaded, LoadError> {
+    let encoding = options.encoding.unwrap_or_else(|| Encoding::sniff(bytes));
+    let bom = bytes.starts_with(encoding.bom()) && !encoding.bom().is_empty();
+    let start = if bom { encoding.bom().len() } else { 0 };
+    let input = &bytes[start..];
+
+    let mut builder = Builder::new(start, options.normalize_newlines);
+    match encoding {
+        Encoding::Utf8 => {
+            let s = std::str::from_utf8(input).map_err(|e| LoadError::Invalid {
+                encoding,
+                offset: start + e.valid_up_to(),
+            })?;
+            builder.extend(s.chars().map(|c| (c, c.len_utf8())));
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            // `is_multiple_of` needs rust 1.87
+            #[allow(clippy::manual_is_multiple_of)]
+            if input.len() % 2 != 0 {
+                return Err(LoadError::OddLength);
+            }
+            let units = input.chunks(2).map(|b| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([b[0], b[1]]),
+                _ => u16::from_be_bytes([b[0], b[1]]),
+            });
+            let mut pos = start;
+            let mut chars = vec![];
+            for c in char::decode_utf16(units) {
+                let c = c.map_err(|_| LoadError::Invalid {
+                    encoding,
+                    offset: pos,
+                })?;
+                pos += c.len_utf16() * 2;
+                chars.push((c, c.len_utf16() * 2));
+            }
+            builder.extend(chars);
+        }
+        Encoding::Latin1 => builder.extend(input.iter().map(|b| (*b as char, 1))),
+    }
+
+    let (text, offsets) = builder.finish();
+    Ok(Loaded {
+        chars: Chars::from_string_in(file, text),
+        encoding,
+        bom,
+        offsets,
+    })
+}
+
+struct Builder {
+    text: String,
+    segments: Vec<Segment>,
+    len: usize,
+    pos: usize,
+    normalize: bool,
+}
+
+impl Builder {
+    fn new(pos: usize, normalize: bool) -> Self {
+        Builder {
+            text: String::new(),
+            segments: vec![],
+            len: 0,
+            pos,
+            normalize,
+        }
+    }
+
+    fn extend(&mut self, chars: impl IntoIterator<Item = (char, usize)>) {
+        let mut chars = chars.into_iter().peekable();
+        while let Some((c, width)) = chars.next() {
+            let (c, width) = self.normalize_newline(c, width, &mut chars);
+            self.push(c, width);
+        }
+    }
+
+    fn normalize_newline<I>(&self, c: char, width: usize, chars: &mut Peekable<I>) -> (char, usize)
+    where
+        I: Iterator<Item = (char, usize)>,
+    {
+        if !self.normalize || c != '\r' {
+            return (c, width);
+        }
+        match chars.peek() {
+            Some(&('\n', w)) => {
+                chars.next();
+                ('\n', width + w)
+            }
+            _ => ('\n', width),
+        }
+    }
+
+    fn push(&mut self, c: char, width: usize) {
+        match self.segments.last() {
+            Some(seg) if seg.width == width => {}
+            _ => self.segments.push(Segment {
+                offset: self.len,
+                pos: self.pos,
+                width,
+            }),
+        }
+        self.text.push(c);
+        self.len += 1;
+        self.pos += width;
+    }
+
+    fn finish(self) -> (String, OffsetMap) {
+        let offsets = OffsetMap {
+            segments: self.segments,
+            len: self.len,
+            byte_len: self.pos,
+        };
+        (self.text, offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_utf8() {
+        let loaded = load("aé\nb".as_bytes(), &LoadOptions::new()).unwrap();
+        assert_eq!(loaded.chars, "aé\nb");
+        assert_eq!(loaded.encoding, Encoding::Utf8);
+        assert!(!loaded.bom);
+        let offsets = &loaded.offsets;
+        assert_eq!(offsets.original_offset(1), Some(1));
+        assert_eq!(offsets.original_offset(2), Some(3));
+        assert_eq!(offsets.original_offset(4), Some(5));
+        assert_eq!(offsets.original_offset(5), None);
+    }
+
+    #[test]
+    fn test_utf8_bom() {
+        let bytes = b"\xEF\xBB\xBFab";
+        let loaded = load(bytes, &LoadOptions::new()).unwrap();
+        assert_eq!(loaded.chars, "ab");
+        assert!(loaded.bom);
+        assert_eq!(loaded.offsets.original_range(Span::new(0, 2)), Some(3..5));
+    }
+
+    #[test]
+    fn test_utf8_invalid() {
+        let err = load(b"ab\xFF", &LoadOptions::new()).unwrap_err();
+        assert_eq!(
+            err,
+            LoadError::Invalid {
+                encoding: Encoding::Utf8,
+                offset: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_utf16() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(utf16le("a😀b"));
+        let loaded = load(&bytes, &LoadOptions::new()).unwrap();
+        assert_eq!(loaded.chars, "a😀b");
+        assert_eq!(loaded.encoding, Encoding::Utf16Le);
+        assert!(loaded.bom);
+        assert_eq!(loaded.offsets.original_offset(1), Some(4));
+        assert_eq!(loaded.offsets.original_offset(2), Some(8));
+        assert_eq!(loaded.offsets.original_offset(3), Some(10));
+
+        let bytes: Vec<u8> = "ab".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let options = LoadOptions::new().encoding(Encoding::Utf16Be);
+        let loaded = load(&bytes, &options).unwrap();
+        assert_eq!(loaded.chars, "ab");
+        assert!(!loaded.bom);
+
+        assert_eq!(
+            load(&[0xFF, 0xFE, 0x61], &LoadOptions::new()).unwrap_err(),
+            LoadError::OddLength
+        );
+        let err = load(&[0xFF, 0xFE, 0x00, 0xD8], &LoadOptions::new()).unwrap_err();
+        assert_eq!(
+            err,
+            LoadError::Invalid {
+                encoding: Encoding::Utf16Le,
+                offset: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_latin1() {
+        let options = LoadOptions::new().encoding(Encoding::Latin1);
+        let loaded = load(b"caf\xE9", &options).unwrap();
+        assert_eq!(loaded.chars, "café");
+        assert_eq!(loaded.offsets.original_offset(4), Some(4));
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let options = LoadOptions::new().normalize_newlines(true);
+        let loaded = load(b"a\r\nb\rc\n", &options).unwrap();
+        assert_eq!(loaded.chars, "a\nb\nc\n");
+        let offsets = &loaded.offsets;
+        assert_eq!(offsets.original_range(Span::new(1, 2)), Some(1..3));
+        assert_eq!(offsets.original_offset(2), Some(3));
+        assert_eq!(offsets.original_offset(4), Some(5));
+        assert_eq!(offsets.original_offset(6), Some(7));
+
+        let loaded = load(b"a\r\nb", &LoadOptions::new()).unwrap();
+        assert_eq!(loaded.chars, "a\r\nb");
+
+        let options = options.encoding(Encoding::Utf16Le);
+        let loaded = load(&utf16le("a\r\nb"), &options).unwrap();
+        assert_eq!(loaded.chars, "a\nb");
+        assert_eq!(loaded.offsets.original_range(Span::new(1, 2)), Some(2..6));
+        assert_eq!(loaded.offsets.original_offset(3), Some(8));
+    }
+}
@@ -0,0 +1,119 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+    /// An estimate, without heap data owned by results.
+    pub bytes: usize,
+}
+
+impl MemoStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+// the farthest failure recorded while the result was parsed, replayed on a hit
+type Failure<T> = Option<(usize, Vec<T>)>;
+
+#[derive(Clone, Debug)]
+struct Entry<T> {
+    result: Arc<dyn Any + Send + Sync>,
+    end: usize,
+    failure: Failure<T>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct MemoTable<T> {
+    entries: HashMap<(&'static str, usize), Entry<T>>,
+    stats: MemoStats,
+}
+
+impl<T> Default for MemoTable<T> {
+    fn default() -> Self {
+        MemoTable {
+            entries: HashMap::new(),
+            stats: MemoStats::default(),
+        }
+    }
+}
+
+impl<T> MemoTable<T> {
+    pub(crate) fn stats(&self) -> MemoStats {
+        self.stats
+    }
+}
+
+impl<T: Clone> MemoTable<T> {
+    // a result of another type is a miss, which happens when two rules share a name
+    pub(crate) fn get<R: Clone + 'static>(
+        &mut self,
+        rule: &'static str,
+        cursor: usize,
+    ) -> Option<(R, usize, Failure<T>)> {
+        let hit = self.entries.get(&(rule, cursor)).and_then(|e| {
+            let result = e.result.downcast_ref::<R>()?;
+            Some((result.clone(), e.end, e.failure.clone()))
+        });
+        match hit {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        hit
+    }
+
+    pub(crate) fn insert<R: Send + Sync + 'static>(
+        &mut self,
+        rule: &'static str,
+        cursor: usize,
+        result: R,
+        end: usize,
+        failure: Failure<T>,
+    ) {
+        let expected = failure.as_ref().map_or(0, |(_, e)| e.len());
+        let entry = Entry {
+            result: Arc::new(result),
+            end,
+            failure,
+        };
+        let size =
+            size_of::<((&str, usize), Entry<T>)>() + size_of::<R>() + expected * size_of::<T>();
+        if self.entries.insert((rule, cursor), entry).is_none() {
+            self.stats.entries += 1;
+            self.stats.bytes += size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let mut table = MemoTable::<char>::default();
+        assert_eq!(table.get::<u32>("a", 0), None);
+        table.insert("a", 0, 7u32, 3, Some((2, vec!['x'])));
+        assert_eq!(table.get::<u32>("a", 0), Some((7, 3, Some((2, vec!['x'])))));
+        assert_eq!(table.get::<u32>("a", 1), None);
+        assert_eq!(table.get::<String>("a", 0), None);
+
+        let stats = table.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.entries, 1);
+        assert!(stats.bytes >= size_of::<u32>());
+        assert_eq!(stats.hit_rate(), 0.25);
+        assert_eq!(MemoStats::default().hit_rate(), 0.0);
+    }
+}
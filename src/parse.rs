@@ -1,16 +1,83 @@
 use crate::ast::N;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
 use crate::chars::Chars;
+use crate::diagnostic::{expected_list, Diagnostic};
+use crate::memo::{MemoStats, MemoTable};
 use crate::node_id::IdGen;
+use crate::pratt::{self, Pratt};
+use crate::sink::{DiagnosticSink, ErrorLimitReached};
+use crate::source_map::{FileId, SourceMap};
 use crate::span::{Span, S};
+#[cfg(feature = "trace")]
+use crate::trace::{Outcome, Trace};
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct ParseCtx<T> {
     chars: Chars,
     tokens: Vec<S<T>>,
     id_gen: IdGen,
+    sink: DiagnosticSink,
     // state
     call_stack: Vec<usize>,
     cursor: usize,
+    // the farthest cursor a `sat` failed at, and what was expected there
+    farthest: Mutex<Option<(usize, Vec<T>)>>,
+    // the farthest cursor looked at or moved to, where `parse_farthest` takes its failure to be
+    reached: AtomicUsize,
+    // `None` unless memoization is on
+    memo: Option<MemoTable<T>>,
+    // seeds of the left recursive rules being grown, `None` while there is none yet
+    seeds: HashMap<(&'static str, usize), Option<Seed>>,
+    // `None` unless tracing is on
+    #[cfg(feature = "trace")]
+    trace: Option<Trace>,
+    // limits, all off by default
+    recursion_limit: Option<usize>,
+    // the fuel given and what is left of it
+    fuel: Option<(usize, AtomicUsize)>,
+    cancel: Option<Arc<AtomicBool>>,
+    // the first limit hit and where, every later rule and `sat` fails with it until
+    // `reset_limits`
+    stopped: Mutex<Option<(Stop, Span)>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Stop {
+    RecursionLimit(usize),
+    FuelExhausted,
+    Cancelled,
+}
+
+type Seed = (Arc<dyn Any + Send + Sync>, usize);
+
+// by hand, the limits and the farthest failure are copied rather than shared
+impl<T: Clone> Clone for ParseCtx<T> {
+    fn clone(&self) -> Self {
+        ParseCtx {
+            chars: self.chars.clone(),
+            tokens: self.tokens.clone(),
+            id_gen: self.id_gen.clone(),
+            sink: self.sink.clone(),
+            call_stack: self.call_stack.clone(),
+            cursor: self.cursor,
+            farthest: Mutex::new(self.farthest.lock().unwrap().clone()),
+            reached: AtomicUsize::new(self.reached.load(Ordering::Relaxed)),
+            memo: self.memo.clone(),
+            seeds: self.seeds.clone(),
+            #[cfg(feature = "trace")]
+            trace: self.trace.clone(),
+            recursion_limit: self.recursion_limit,
+            fuel: (self.fuel.as_ref())
+                .map(|(fuel, left)| (*fuel, AtomicUsize::new(left.load(Ordering::Relaxed)))),
+            cancel: self.cancel.clone(),
+            stopped: Mutex::new(*self.stopped()),
+        }
+    }
 }
 
 impl<T> ParseCtx<T> {
@@ -19,8 +86,219 @@ impl<T> ParseCtx<T> {
             chars,
             tokens,
             id_gen: IdGen::new(),
+            sink: DiagnosticSink::new(),
             call_stack: vec![],
             cursor: 0,
+            farthest: Mutex::new(None),
+            reached: AtomicUsize::new(0),
+            memo: None,
+            seeds: HashMap::new(),
+            #[cfg(feature = "trace")]
+            trace: None,
+            recursion_limit: None,
+            fuel: None,
+            cancel: None,
+            stopped: Mutex::new(None),
+        }
+    }
+
+    pub fn new_in(source_map: &SourceMap, file: FileId, tokens: Vec<S<T>>) -> Self {
+        let chars = source_map.chars(file).expect("file not in source map");
+        Self::new(chars.clone(), tokens)
+    }
+
+    pub fn with_sink(mut self, sink: DiagnosticSink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    pub fn sink(&self) -> &DiagnosticSink {
+        &self.sink
+    }
+
+    pub fn memoize(mut self, on: bool) -> Self {
+        self.memo = on.then(MemoTable::default);
+        self
+    }
+
+    pub fn memo_stats(&self) -> MemoStats {
+        self.memo.as_ref().map(|m| m.stats()).unwrap_or_default()
+    }
+
+    /// Counts the rules of `Parse::parse`, `Parse::parse_recover` and pratt operands.
+    pub fn recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = Some(limit);
+        self
+    }
+
+    /// Every look at a token uses a unit.
+    pub fn fuel(mut self, fuel: usize) -> Self {
+        self.fuel = Some((fuel, AtomicUsize::new(fuel)));
+        self
+    }
+
+    pub fn cancel_on(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    pub fn fuel_left(&self) -> Option<usize> {
+        self.fuel
+            .as_ref()
+            .map(|(_, left)| left.load(Ordering::Relaxed))
+    }
+
+    /// A limit which was hit is kept until this is called, which also refills the fuel.
+    pub fn reset_limits(&mut self) {
+        *self.stopped() = None;
+        if let Some((fuel, left)) = &self.fuel {
+            left.store(*fuel, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "trace")]
+    pub fn trace(mut self, on: bool) -> Self {
+        self.trace = on.then(Trace::new);
+        self
+    }
+
+    #[cfg(feature = "trace")]
+    pub fn trace_log(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    #[cfg(feature = "trace")]
+    pub fn trace_log_mut(&mut self) -> Option<&mut Trace> {
+        self.trace.as_mut()
+    }
+}
+
+/// Usable as `Parse::Error` with `parse_errors!`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParseError<T> {
+    Expect {
+        expected: Vec<T>,
+        found: S<T>,
+    },
+    /// `span` is the end of the input.
+    Eof {
+        expected: Vec<T>,
+        span: Span,
+    },
+    /// The rule called itself before matching anything.
+    LeftRecursion {
+        rule: &'static str,
+        span: Span,
+    },
+    RecursionLimit {
+        limit: usize,
+        span: Span,
+    },
+    FuelExhausted {
+        span: Span,
+    },
+    Cancelled {
+        span: Span,
+    },
+}
+
+impl<T> ParseError<T> {
+    pub fn new(expected: Vec<T>, found: Option<S<T>>, eof: Span) -> Self {
+        match found {
+            Some(found) => ParseError::Expect { expected, found },
+            None => ParseError::Eof {
+                expected,
+                span: eof,
+            },
+        }
+    }
+
+    pub fn expected(&self) -> &[T] {
+        match self {
+            ParseError::Expect { expected, .. } | ParseError::Eof { expected, .. } => expected,
+            _ => &[],
+        }
+    }
+
+    pub fn found(&self) -> Option<&S<T>> {
+        match self {
+            ParseError::Expect { found, .. } => Some(found),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Expect { found, .. } => found.span,
+            ParseError::Eof { span, .. }
+            | ParseError::LeftRecursion { span, .. }
+            | ParseError::RecursionLimit { span, .. }
+            | ParseError::FuelExhausted { span }
+            | ParseError::Cancelled { span } => *span,
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ParseError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = expected_list(self.expected());
+        match self {
+            ParseError::Expect { found, .. } => {
+                write!(f, "expected {}, found {}", expected, found.tok)
+            }
+            ParseError::Eof { .. } => write!(f, "expected {}, found end of file", expected),
+            ParseError::LeftRecursion { rule, .. } => {
+                write!(f, "left recursive rule `{}` has no base case here", rule)
+            }
+            ParseError::RecursionLimit { limit, .. } => {
+                write!(f, "nesting exceeds the limit of {}", limit)
+            }
+            ParseError::FuelExhausted { .. } => write!(f, "parsing ran out of fuel"),
+            ParseError::Cancelled { .. } => write!(f, "parsing was cancelled"),
+        }
+    }
+}
+
+impl<T: fmt::Debug + fmt::Display> std::error::Error for ParseError<T> {}
+
+impl<T: fmt::Display> From<ParseError<T>> for Diagnostic {
+    fn from(e: ParseError<T>) -> Self {
+        match e {
+            ParseError::Expect { .. } | ParseError::Eof { .. } => {
+                Diagnostic::expected(e.expected(), e.found(), e.span())
+            }
+            _ => Diagnostic::error(e.to_string()).with_primary(e.span(), ""),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recovered<T, E> {
+    pub value: T,
+    pub errors: Vec<E>,
+}
+
+impl<T, E> Recovered<T, E> {
+    pub fn new(value: T) -> Self {
+        Recovered {
+            value,
+            errors: vec![],
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn take(self, errors: &mut Vec<E>) -> T {
+        errors.extend(self.errors);
+        self.value
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Recovered<U, E> {
+        Recovered {
+            value: f(self.value),
+            errors: self.errors,
         }
     }
 }
@@ -122,6 +400,34 @@ macro_rules! parse_many_after {
     }};
 }
 
+/// The error methods of `Parse` for an `Error` converting from `ParseError` and `Clone` tokens.
+#[macro_export]
+macro_rules! parse_errors {
+    () => {
+        fn expect_err(
+            &self,
+            expected: Self::Token,
+            found: Option<$crate::span::S<Self::Token>>,
+        ) -> Self::Error {
+            let eof = $crate::parse::Parse::eof_span(self);
+            $crate::parse::ParseError::new(vec![expected], found, eof).into()
+        }
+
+        fn expect_one_of_err(
+            &self,
+            expected: &[Self::Token],
+            found: Option<$crate::span::S<Self::Token>>,
+        ) -> Self::Error {
+            let eof = $crate::parse::Parse::eof_span(self);
+            $crate::parse::ParseError::new(expected.to_vec(), found, eof).into()
+        }
+
+        fn parse_err(&self, e: $crate::parse::ParseError<Self::Token>) -> Self::Error {
+            e.into()
+        }
+    };
+}
+
 pub trait Parse {
     type Error;
     type Token;
@@ -137,28 +443,69 @@ pub trait Parse {
         found: Option<S<Self::Token>>,
     ) -> Self::Error;
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    // errors
+
+    /// For limits and left recursion. `Self::Error` may not hold a `ParseError`, so by default it
+    /// is an expectation of nothing, found at the token where parsing stopped. A parser with an
+    /// error of its own should override this to keep the cause, as `parse_errors!` does.
+    fn parse_err(&self, e: ParseError<Self::Token>) -> Self::Error
+    where
+        Self::Token: Clone,
+    {
+        let found = self.ctx().token_at(e.span()).cloned();
+        self.expect_one_of_err(&[], found)
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // parsing
 
     fn parse<T>(
         &mut self,
         f: impl Fn(&mut Self) -> Result<T, Self::Error>,
-    ) -> Result<T, Self::Error> {
+    ) -> Result<T, Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        self.parse_rule("parse", f)
+    }
+
+    /// `rule` names it in the trace.
+    fn parse_rule<T>(
+        &mut self,
+        rule: &'static str,
+        f: impl Fn(&mut Self) -> Result<T, Self::Error>,
+    ) -> Result<T, Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        self.ctx()
+            .check_limits(true)
+            .map_err(|e| self.parse_err(e))?;
         let f = |parser: &mut Self| {
             parser.ctx_mut().push_stack();
 
             let ret = f(parser);
 
             parser.ctx_mut().pop_stack();
-            ret
+            // a limit hit inside of `f` fails the rule, even if `f` did not notice
+            let stopped = parser.ctx().stop_err().map_err(|e| parser.parse_err(e));
+            ret.and_then(|d| stopped.map(|()| d))
         };
-        self.parse_roll_back(f)
+        // outside of `parse_roll_back`, which may fail without calling `f`
+        self.ctx_mut().trace_enter(rule);
+        let ret = self.parse_roll_back(f);
+        self.ctx_mut().trace_exit(ret.is_ok());
+        ret
     }
 
     fn parse_n<T>(
         &mut self,
         f: impl Fn(&mut Self) -> Result<T, Self::Error>,
-    ) -> Result<N<T>, Self::Error> {
+    ) -> Result<N<T>, Self::Error>
+    where
+        Self::Token: Clone,
+    {
         let f = |parser: &mut Self| match f(parser) {
             Ok(d) => Ok(parser.ctx().make_node(d)),
             Err(e) => Err(e),
@@ -166,26 +513,196 @@ pub trait Parse {
         self.parse(f)
     }
 
+    /// Every operator application is a node spanning its operator and operands.
+    fn parse_pratt<E>(
+        &mut self,
+        pratt: &Pratt<Self::Token, E>,
+        atom: impl Fn(&mut Self) -> Result<N<E>, Self::Error>,
+    ) -> Result<N<E>, Self::Error>
+    where
+        Self::Token: Clone + Eq,
+    {
+        self.parse_roll_back(|p: &mut Self| pratt::parse_expr(p, pratt, &atom, 0))
+    }
+
     fn parse_roll_back<T>(
         &mut self,
         f: impl Fn(&mut Self) -> Result<T, Self::Error>,
-    ) -> Result<T, Self::Error> {
+    ) -> Result<T, Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        // after a limit was hit every attempt fails at once
+        if let Err(e) = self.ctx().check_limits(false) {
+            return Err(self.parse_err(e));
+        }
         let cursor = self.ctx().cursor;
         match f(self) {
             Ok(d) => Ok(d),
             Err(e) => {
+                self.ctx_mut().trace_roll_back(cursor);
                 self.ctx_mut().cursor = cursor;
                 Err(e)
             }
         }
     }
 
+    /// `rule` must name one rule only. The cache is not used while a `parse_left_rec` grows, nor
+    /// once a limit is hit.
+    fn parse_memo<T>(
+        &mut self,
+        rule: &'static str,
+        f: impl Fn(&mut Self) -> Result<T, Self::Error>,
+    ) -> Result<T, Self::Error>
+    where
+        T: Clone + Send + Sync + 'static,
+        Self::Error: Clone + Send + Sync + 'static,
+        Self::Token: Clone + Eq,
+    {
+        let cursor = self.ctx().cursor;
+        let growing = !self.ctx().seeds.is_empty();
+        let stopped = self.ctx().stop_err().is_err();
+        let memo = match &mut self.ctx_mut().memo {
+            Some(memo) if !growing && !stopped => memo,
+            _ => return self.parse_roll_back(f),
+        };
+        if let Some((result, end, failure)) = memo.get::<Result<T, Self::Error>>(rule, cursor) {
+            if let Some((at, expected)) = failure {
+                self.ctx().record_failure_at(at, &expected);
+            }
+            self.ctx_mut().cursor = end;
+            return result;
+        }
+
+        // the failures of `f` alone are kept with the result, then merged with the ones before
+        let before = self.ctx().farthest.lock().unwrap().take();
+        let result = self.parse_roll_back(f);
+        let end = self.ctx().cursor;
+        let failure = std::mem::replace(&mut *self.ctx().farthest.lock().unwrap(), before);
+        if let Some((at, expected)) = &failure {
+            self.ctx().record_failure_at(*at, expected);
+        }
+        if self.ctx().stop_err().is_err() {
+            return result;
+        }
+        if let Some(memo) = &mut self.ctx_mut().memo {
+            memo.insert(rule, cursor, result.clone(), end, failure);
+        }
+        result
+    }
+
+    /// On an error skips to one of `sync`, at least one token, and returns a node made by `error`.
+    fn parse_recover<A>(
+        &mut self,
+        sync: &[Self::Token],
+        f: impl Fn(&mut Self) -> Result<N<A>, Self::Error>,
+        error: impl FnOnce(&Self::Error) -> A,
+    ) -> Recovered<N<A>, Self::Error>
+    where
+        Self::Token: Clone + Eq,
+    {
+        let start = self.ctx().cursor;
+        let limits = self.ctx().check_limits(true);
+        self.ctx_mut().push_stack();
+        let result = match limits {
+            Ok(()) => f(self),
+            Err(e) => Err(self.parse_err(e)),
+        };
+        // a limit hit inside of `f` fails it, as in `parse_rule`
+        let stopped = self.ctx().stop_err().map_err(|e| self.parse_err(e));
+        let ret = match result.and_then(|node| stopped.map(|()| node)) {
+            Ok(node) => Recovered::new(node),
+            Err(e) => {
+                self.synchronize(sync);
+                if self.ctx().cursor == start {
+                    self.advance();
+                }
+                let node = self.make_node(error(&e));
+                Recovered {
+                    value: node,
+                    errors: vec![e],
+                }
+            }
+        };
+        self.ctx_mut().pop_stack();
+        ret
+    }
+
+    /// `f` must try the alternatives in order and roll back failed ones. A cycle through other
+    /// rules works too, as long as one of them uses `parse_left_rec`.
+    fn parse_left_rec<T>(
+        &mut self,
+        rule: &'static str,
+        f: impl Fn(&mut Self) -> Result<N<T>, Self::Error>,
+    ) -> Result<N<T>, Self::Error>
+    where
+        T: Clone + Send + Sync + 'static,
+        Self::Token: Clone,
+    {
+        let start = self.ctx().cursor;
+        let key = (rule, start);
+        if let Some(seed) = self.ctx().seeds.get(&key) {
+            return match seed {
+                Some((node, end)) => {
+                    let node = node.downcast_ref::<N<T>>().expect("rule name used twice");
+                    let node = node.clone();
+                    self.ctx_mut().cursor = *end;
+                    Ok(node)
+                }
+                None => {
+                    let span = self.peek().map_or_else(|| self.eof_span(), |d| d.span);
+                    Err(self.parse_err(ParseError::LeftRecursion { rule, span }))
+                }
+            };
+        }
+
+        self.ctx_mut().seeds.insert(key, None);
+        let mut grown: Option<(N<T>, usize)> = None;
+        let mut error = None;
+        loop {
+            self.ctx_mut().cursor = start;
+            match f(self) {
+                Ok(node) => {
+                    let end = self.ctx().cursor;
+                    if matches!(&grown, Some((_, last)) if end <= *last) {
+                        break;
+                    }
+                    let seed: Seed = (Arc::new(node.clone()), end);
+                    self.ctx_mut().seeds.insert(key, Some(seed));
+                    grown = Some((node, end));
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        self.ctx_mut().seeds.remove(&key);
+
+        match grown {
+            Some((node, end)) => {
+                self.ctx_mut().cursor = end;
+                Ok(node)
+            }
+            None => {
+                self.ctx_mut().cursor = start;
+                Err(error.unwrap())
+            }
+        }
+    }
+
     fn parse_roll_back_opt<T>(
         &mut self,
         f: impl Fn(&mut Self) -> Result<Option<T>, Self::Error>,
-    ) -> Result<Option<T>, Self::Error> {
+    ) -> Result<Option<T>, Self::Error>
+    where
+        Self::Token: Clone,
+    {
         let cursor = self.ctx().cursor;
-        match f(self) {
+        let ret = f(self);
+        // a limit hit inside of `f` fails it, as in `parse_rule`
+        let stopped = self.ctx().stop_err().map_err(|e| self.parse_err(e));
+        match ret.and_then(|d| stopped.map(|()| d)) {
             Ok(Some(d)) => Ok(Some(d)),
             d => {
                 self.ctx_mut().cursor = cursor;
@@ -242,10 +759,10 @@ pub trait Parse {
         Self::Token: Clone,
     {
         let current = match self.peek() {
-            Some(d) => d.tok,
+            Some(d) => cond(&d.tok),
             None => return Ok(None),
         };
-        if cond(&current) {
+        if current {
             let t = f(self)?;
             Ok(Some(t))
         } else {
@@ -270,17 +787,67 @@ pub trait Parse {
         self.ctx_mut().advance_cmp(tok)
     }
 
-    fn peek(&self) -> Option<S<Self::Token>>
+    fn peek(&self) -> Option<&S<Self::Token>> {
+        self.ctx().peek()
+    }
+
+    fn peek_nth(&self, k: usize) -> Option<&S<Self::Token>> {
+        self.ctx().peek_nth(k)
+    }
+
+    fn peek_kinds(&self, kinds: &[Self::Token]) -> bool
     where
-        Self::Token: Clone,
+        Self::Token: Eq,
     {
-        self.ctx().peek()
+        self.ctx().peek_kinds(kinds)
+    }
+
+    fn prev(&self) -> Option<&S<Self::Token>> {
+        self.ctx().prev()
     }
 
     fn eof(&self) -> bool {
         self.ctx().eof()
     }
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    // recovery
+
+    /// Stops before one of `sync`.
+    fn synchronize(&mut self, sync: &[Self::Token]) -> Option<Span>
+    where
+        Self::Token: Clone + Eq,
+    {
+        let start = self.ctx().cursor;
+        while let Some(d) = self.peek() {
+            if sync.contains(&d.tok) {
+                break;
+            }
+            self.advance();
+        }
+        self.ctx().span_from(start)
+    }
+
+    /// `open` was already consumed, the matching `close` is skipped too.
+    fn skip_to_closer(&mut self, open: Self::Token, close: Self::Token) -> Option<Span>
+    where
+        Self::Token: Clone + Eq,
+    {
+        let start = self.ctx().cursor;
+        let mut depth = 1;
+        while let Some(d) = self.advance() {
+            if d.tok == open {
+                depth += 1;
+            } else if d.tok == close {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+        self.ctx().span_from(start)
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // expect
 
@@ -297,14 +864,16 @@ pub trait Parse {
     where
         Self::Token: Eq + Clone,
     {
-        let d = match self.peek() {
-            Some(d) => d,
-            None => return Err(self.expect_err(expected, None)),
-        };
-        if d.tok == expected {
-            Ok(d)
-        } else {
-            Err(self.expect_err(expected, Some(d)))
+        let peeked = self.peek();
+        self.ctx()
+            .check_limits(false)
+            .map_err(|e| self.parse_err(e))?;
+        match peeked {
+            Some(d) if d.tok == expected => Ok(d.clone()),
+            found => {
+                self.ctx().record_failure(std::slice::from_ref(&expected));
+                Err(self.expect_err(expected, found.cloned()))
+            }
         }
     }
 
@@ -321,18 +890,70 @@ pub trait Parse {
     where
         Self::Token: Eq + Clone,
     {
-        let d = match self.peek() {
-            Some(d) => d,
-            None => return Err(self.expect_one_of_err(expected, None)),
-        };
-
-        if expected.iter().find(|e| *e == &d.tok).is_some() {
-            Ok(d)
-        } else {
-            Err(self.expect_one_of_err(expected, Some(d)))
+        let peeked = self.peek();
+        self.ctx()
+            .check_limits(false)
+            .map_err(|e| self.parse_err(e))?;
+        match peeked {
+            Some(d) if expected.contains(&d.tok) => Ok(d.clone()),
+            found => {
+                self.ctx().record_failure(expected);
+                Err(self.expect_one_of_err(expected, found.cloned()))
+            }
         }
     }
 
+    /// Alternatives which were rolled back count too.
+    fn farthest_error(&self) -> Option<Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        let (cursor, expected) = self.ctx().farthest_failure()?;
+        let found = self.ctx().tokens.get(cursor).cloned();
+        Some(self.expect_one_of_err(&expected, found))
+    }
+
+    /// Failures recorded before are forgotten. The error of `f` is kept when a limit is hit, or
+    /// when `f` got past the farthest failure, as with an error made by hand after more tokens.
+    fn parse_farthest<T>(
+        &mut self,
+        f: impl Fn(&mut Self) -> Result<T, Self::Error>,
+    ) -> Result<T, Self::Error>
+    where
+        Self::Token: Clone,
+    {
+        self.ctx_mut().clear_farthest();
+        let cursor = self.ctx().cursor;
+        let reached = self.ctx().reached.swap(cursor, Ordering::Relaxed);
+        let ret = f(self);
+        let failed_at = self.ctx().reached.fetch_max(reached, Ordering::Relaxed);
+        let failed_at = failed_at.max(self.ctx().cursor);
+        ret.map_err(|e| {
+            let farthest = self.ctx().farthest_failure();
+            match farthest {
+                Some((at, _)) if at >= failed_at && self.ctx().stop_err().is_ok() => {
+                    self.farthest_error().unwrap_or(e)
+                }
+                _ => e,
+            }
+        })
+    }
+
+    fn expect_diagnostic(
+        &self,
+        expected: &[Self::Token],
+        found: Option<&S<Self::Token>>,
+    ) -> Diagnostic
+    where
+        Self::Token: fmt::Display,
+    {
+        Diagnostic::expected(expected, found, self.eof_span())
+    }
+
+    fn emit(&self, diag: Diagnostic) -> Result<(), ErrorLimitReached> {
+        self.ctx().sink().emit(diag)
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // meta
 
@@ -340,13 +961,30 @@ pub trait Parse {
         self.ctx().span()
     }
 
+    fn point(&self) -> Span {
+        self.ctx().point()
+    }
+
+    fn eof_span(&self) -> Span {
+        self.ctx().eof_span()
+    }
+
     fn make_node<A>(&self, data: A) -> N<A> {
         self.ctx().make_node(data)
     }
 
+    /// Works outside of the parsing context, e.g. when desugaring.
+    fn make_node_at<A>(&self, span: Span, data: A) -> N<A> {
+        self.ctx().make_node_at(span, data)
+    }
+
     fn get_string(&self) -> Option<String> {
+        self.get_str().map(|s| s.to_string())
+    }
+
+    fn get_str(&self) -> Option<&str> {
         let span = self.span()?;
-        self.chars().get_string(span)
+        self.chars().get_str(span)
     }
 
     fn chars(&self) -> &Chars {
@@ -361,7 +999,11 @@ pub trait Parse {
 
 impl<T> ParseCtx<T> {
     fn span(&self) -> Option<Span> {
-        let start = *self.call_stack.last()?;
+        self.span_from(*self.call_stack.last()?)
+    }
+
+    // the span of the tokens from `start` to the cursor
+    fn span_from(&self, start: usize) -> Option<Span> {
         debug_assert!(start <= self.cursor);
         if start == self.cursor {
             None
@@ -372,17 +1014,164 @@ impl<T> ParseCtx<T> {
         }
     }
 
-    fn push_stack(&mut self) {
+    // the empty span right after the last consumed token
+    fn point(&self) -> Span {
+        let file = self.chars.file();
+        let pos = if self.cursor > 0 {
+            self.tokens[self.cursor - 1].span.end()
+        } else {
+            self.tokens.first().map(|t| t.span.start()).unwrap_or(0)
+        };
+        Span::point_in(file, pos)
+    }
+
+    fn eof_span(&self) -> Span {
+        Span::point_in(self.chars.file(), self.chars.len())
+    }
+
+    // the tracing hooks are empty without the `trace` feature
+
+    #[allow(unused_variables)]
+    fn trace_enter(&mut self, rule: &'static str) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.enter(rule, self.cursor);
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn trace_exit(&mut self, ok: bool) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            let outcome = if ok {
+                Outcome::Success
+            } else {
+                Outcome::Failure
+            };
+            trace.exit(self.cursor, outcome);
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn trace_roll_back(&mut self, to: usize) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.roll_back(self.cursor, to);
+        }
+    }
+
+    // fail if a limit is hit now or was before, entering a rule checks the nesting too
+    pub(crate) fn check_limits(&self, entering: bool) -> Result<(), ParseError<T>> {
+        let depth = self.call_stack.len();
+        match self.recursion_limit {
+            Some(limit) if entering && depth >= limit => self.stop(Stop::RecursionLimit(limit)),
+            _ => self.check_cancel(),
+        }
+        self.stop_err()
+    }
+
+    // the limit which was hit, if any
+    fn stop_err(&self) -> Result<(), ParseError<T>> {
+        match *self.stopped() {
+            Some((Stop::RecursionLimit(limit), span)) => {
+                Err(ParseError::RecursionLimit { limit, span })
+            }
+            Some((Stop::FuelExhausted, span)) => Err(ParseError::FuelExhausted { span }),
+            Some((Stop::Cancelled, span)) => Err(ParseError::Cancelled { span }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_cancel(&self) {
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|c| c.load(Ordering::Relaxed))
+        {
+            self.stop(Stop::Cancelled);
+        }
+    }
+
+    // uses up a unit of fuel
+    fn burn_fuel(&self) {
+        self.check_cancel();
+        if let Some((_, left)) = &self.fuel {
+            let burnt =
+                left.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            if burnt.is_err() {
+                self.stop(Stop::FuelExhausted);
+            }
+        }
+    }
+
+    // the first limit hit sticks
+    fn stop(&self, stop: Stop) {
+        let mut stopped = self.stopped();
+        if stopped.is_none() {
+            let span = match self.tokens.get(self.cursor) {
+                Some(t) => t.span,
+                None => self.eof_span(),
+            };
+            *stopped = Some((stop, span));
+        }
+    }
+
+    fn stopped(&self) -> MutexGuard<'_, Option<(Stop, Span)>> {
+        self.stopped.lock().unwrap()
+    }
+
+    fn peek(&self) -> Option<&S<T>> {
+        self.peek_nth(0)
+    }
+
+    // looking at a token uses up a unit of fuel
+    fn peek_nth(&self, k: usize) -> Option<&S<T>> {
+        let d = self.tokens.get(self.cursor + k)?;
+        self.burn_fuel();
+        self.reach();
+        Some(d)
+    }
+
+    fn reach(&self) {
+        self.reached.fetch_max(self.cursor, Ordering::Relaxed);
+    }
+
+    fn peek_kinds(&self, kinds: &[T]) -> bool
+    where
+        T: Eq,
+    {
+        (0..kinds.len()).all(|k| matches!(self.peek_nth(k), Some(d) if d.tok == kinds[k]))
+    }
+
+    // `None` at the end of the input
+    fn token_at(&self, span: Span) -> Option<&S<T>> {
+        let i = self
+            .tokens
+            .partition_point(|t| t.span.start() < span.start());
+        self.tokens.get(i).filter(|t| t.span == span)
+    }
+
+    fn prev(&self) -> Option<&S<T>> {
+        self.tokens[..self.cursor].last()
+    }
+
+    pub(crate) fn push_stack(&mut self) {
         self.call_stack.push(self.cursor)
     }
 
-    fn pop_stack(&mut self) -> Option<usize> {
+    pub(crate) fn pop_stack(&mut self) -> Option<usize> {
         self.call_stack.pop()
     }
 
     fn make_node<A>(&self, data: A) -> N<A> {
+        assert!(!self.call_stack.is_empty(), "not in parsing context");
+        let id = self.id_gen.next();
+        let span = self.span().unwrap_or_else(|| self.point());
+        N { id, span, data }
+    }
+
+    fn make_node_at<A>(&self, span: Span, data: A) -> N<A> {
         let id = self.id_gen.next();
-        let span = self.span().expect("not in parsing context");
         N { id, span, data }
     }
 
@@ -396,10 +1185,11 @@ impl<T> ParseCtx<T> {
 }
 
 impl<T: Clone> ParseCtx<T> {
-    fn advance_if(&mut self, p: impl Fn(T) -> bool) -> bool {
+    fn advance_if(&mut self, p: impl Fn(&T) -> bool) -> bool {
         if let Some(c) = self.peek() {
-            if p(c.tok) {
+            if p(&c.tok) {
                 self.cursor += 1;
+                self.reach();
                 return true;
             }
         }
@@ -412,22 +1202,43 @@ impl<T: Clone> ParseCtx<T> {
         } else {
             let c = self.tokens[self.cursor].clone();
             self.cursor += 1;
+            self.reach();
             Some(c)
         }
     }
+}
 
-    fn peek(&self) -> Option<S<T>> {
-        if self.eof() {
-            None
-        } else {
-            let c = self.tokens[self.cursor].clone();
-            Some(c)
-        }
+impl<T: Clone> ParseCtx<T> {
+    pub fn farthest_failure(&self) -> Option<(usize, Vec<T>)> {
+        self.farthest.lock().unwrap().clone()
+    }
+
+    pub fn clear_farthest(&mut self) {
+        *self.farthest.lock().unwrap() = None;
     }
 }
 
 impl<T: Clone + Eq> ParseCtx<T> {
     fn advance_cmp(&mut self, tok: T) -> bool {
-        self.advance_if(|x| x == tok)
+        self.advance_if(|x| *x == tok)
+    }
+
+    fn record_failure(&self, expected: &[T]) {
+        self.record_failure_at(self.cursor, expected)
+    }
+
+    fn record_failure_at(&self, at: usize, expected: &[T]) {
+        let mut farthest = self.farthest.lock().unwrap();
+        match &mut *farthest {
+            Some((cursor, _)) if *cursor > at => {}
+            Some((cursor, merged)) if *cursor == at => {
+                for tok in expected {
+                    if !merged.contains(tok) {
+                        merged.push(tok.clone());
+                    }
+                }
+            }
+            _ => *farthest = Some((at, expected.to_vec())),
+        }
     }
 }
@@ -0,0 +1,171 @@
+use crate::ast::N;
+use crate::parse::Parse;
+use crate::span::S;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+type PrefixFn<T, E> = Box<dyn Fn(S<T>, N<E>) -> E>;
+type InfixFn<T, E> = Box<dyn Fn(N<E>, S<T>, N<E>) -> E>;
+type PostfixFn<T, E> = Box<dyn Fn(N<E>, S<T>) -> E>;
+
+/// A higher precedence binds tighter. At the same precedence postfix binds tighter than prefix,
+/// and prefix tighter than infix.
+pub struct Pratt<T, E> {
+    prefix: Vec<(T, u32, PrefixFn<T, E>)>,
+    infix: Vec<(T, u32, Assoc, InfixFn<T, E>)>,
+    postfix: Vec<(T, u32, PostfixFn<T, E>)>,
+}
+
+impl<T, E> Default for Pratt<T, E> {
+    fn default() -> Self {
+        Pratt {
+            prefix: vec![],
+            infix: vec![],
+            postfix: vec![],
+        }
+    }
+}
+
+impl<T: Eq, E> Pratt<T, E> {
+    pub fn new() -> Self {
+        Pratt::default()
+    }
+
+    pub fn prefix(mut self, tok: T, prec: u32, f: impl Fn(S<T>, N<E>) -> E + 'static) -> Self {
+        self.prefix.push((tok, prec, Box::new(f)));
+        self
+    }
+
+    pub fn infix(
+        mut self,
+        tok: T,
+        prec: u32,
+        assoc: Assoc,
+        f: impl Fn(N<E>, S<T>, N<E>) -> E + 'static,
+    ) -> Self {
+        self.infix.push((tok, prec, assoc, Box::new(f)));
+        self
+    }
+
+    pub fn postfix(mut self, tok: T, prec: u32, f: impl Fn(N<E>, S<T>) -> E + 'static) -> Self {
+        self.postfix.push((tok, prec, Box::new(f)));
+        self
+    }
+
+    // binding powers are `3 * prec` plus 0 or 1 on the sides of infix operators, so a side
+    // binds an operand if its power is below the one of the next operator
+
+    fn prefix_op(&self, tok: &T) -> Option<(u32, &PrefixFn<T, E>)> {
+        let (_, prec, f) = self.prefix.iter().find(|(t, _, _)| t == tok)?;
+        Some((3 * prec + 2, f))
+    }
+
+    fn infix_op(&self, tok: &T) -> Option<(u32, u32, &InfixFn<T, E>)> {
+        let (_, prec, assoc, f) = self.infix.iter().find(|(t, _, _, _)| t == tok)?;
+        let (l, r) = match assoc {
+            Assoc::Left => (3 * prec, 3 * prec + 1),
+            Assoc::Right => (3 * prec + 1, 3 * prec),
+        };
+        Some((l, r, f))
+    }
+
+    fn postfix_op(&self, tok: &T) -> Option<(u32, &PostfixFn<T, E>)> {
+        let (_, prec, f) = self.postfix.iter().find(|(t, _, _)| t == tok)?;
+        Some((3 * prec + 2, f))
+    }
+}
+
+// operands nest like rules, so they count towards the recursion limit
+pub(crate) fn parse_expr<P, E>(
+    parser: &mut P,
+    pratt: &Pratt<P::Token, E>,
+    atom: &impl Fn(&mut P) -> Result<N<E>, P::Error>,
+    min_bp: u32,
+) -> Result<N<E>, P::Error>
+where
+    P: Parse + ?Sized,
+    P::Token: Clone + Eq,
+{
+    if let Err(e) = parser.ctx().check_limits(true) {
+        return Err(parser.parse_err(e));
+    }
+    parser.ctx_mut().push_stack();
+    let ret = parse_operand(parser, pratt, atom, min_bp);
+    parser.ctx_mut().pop_stack();
+    ret
+}
+
+fn parse_operand<P, E>(
+    parser: &mut P,
+    pratt: &Pratt<P::Token, E>,
+    atom: &impl Fn(&mut P) -> Result<N<E>, P::Error>,
+    min_bp: u32,
+) -> Result<N<E>, P::Error>
+where
+    P: Parse + ?Sized,
+    P::Token: Clone + Eq,
+{
+    let prefix = parser.peek().and_then(|op| pratt.prefix_op(&op.tok));
+    let mut lhs = match prefix {
+        Some((r_bp, f)) => {
+            let op = parser.advance().unwrap();
+            let rhs = parse_expr(parser, pratt, atom, r_bp)?;
+            let span = op.span.merge(rhs.span);
+            parser.make_node_at(span, f(op, rhs))
+        }
+        None => atom(parser)?,
+    };
+
+    while let Some(op) = parser.peek() {
+        if let Some((l_bp, f)) = pratt.postfix_op(&op.tok) {
+            if l_bp < min_bp {
+                break;
+            }
+            let op = parser.advance().unwrap();
+            let span = lhs.span.merge(op.span);
+            lhs = parser.make_node_at(span, f(lhs, op));
+        } else if let Some((l_bp, r_bp, f)) = pratt.infix_op(&op.tok) {
+            if l_bp < min_bp {
+                break;
+            }
+            let op = parser.advance().unwrap();
+            let rhs = parse_expr(parser, pratt, atom, r_bp)?;
+            let span = lhs.span.merge(rhs.span);
+            lhs = parser.make_node_at(span, f(lhs, op, rhs));
+        } else {
+            break;
+        }
+    }
+    Ok(lhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binding_power() {
+        let pratt: Pratt<char, ()> = Pratt::new()
+            .prefix('-', 2, |_, _| ())
+            .infix('+', 1, Assoc::Left, |_, _, _| ())
+            .infix('*', 2, Assoc::Left, |_, _, _| ())
+            .infix('^', 3, Assoc::Right, |_, _, _| ())
+            .postfix('!', 2, |_, _| ());
+        let infix = |c| {
+            let (l, r, _) = pratt.infix_op(&c).unwrap();
+            (l, r)
+        };
+        assert!(infix('+').1 < infix('*').0);
+        assert!(infix('*').0 < infix('*').1);
+        assert!(infix('^').0 > infix('^').1);
+        let (prefix, _) = pratt.prefix_op(&'-').unwrap();
+        assert!(prefix > infix('*').0);
+        assert!(prefix < infix('^').0);
+        assert_eq!(pratt.postfix_op(&'!').unwrap().0, prefix);
+        assert!(pratt.prefix_op(&'+').is_none());
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub explanation: &'static str,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+impl From<ErrorCode> for String {
+    fn from(code: ErrorCode) -> Self {
+        code.code.to_string()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Registry {
+    codes: &'static [ErrorCode],
+}
+
+impl Registry {
+    pub const fn new(codes: &'static [ErrorCode]) -> Self {
+        Registry { codes }
+    }
+
+    pub fn codes(&self) -> &'static [ErrorCode] {
+        self.codes
+    }
+
+    pub fn get(&self, code: &str) -> Option<&'static ErrorCode> {
+        self.codes.iter().find(|c| c.code == code)
+    }
+
+    pub fn explain(&self, code: &str) -> Option<&'static str> {
+        self.get(code).map(|c| c.explanation)
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        self.get(code).is_some()
+    }
+
+    pub fn unregistered<'a>(
+        &self,
+        diags: impl IntoIterator<Item = &'a Diagnostic>,
+    ) -> Vec<&'a str> {
+        let mut ret = vec![];
+        for code in diags.into_iter().filter_map(|d| d.code.as_deref()) {
+            if !self.contains(code) && !ret.contains(&code) {
+                ret.push(code);
+            }
+        }
+        ret
+    }
+
+    pub fn duplicates(&self) -> Vec<&'static str> {
+        let mut ret = vec![];
+        for (i, c) in self.codes.iter().enumerate() {
+            if self.codes[..i].iter().any(|d| d.code == c.code) && !ret.contains(&c.code) {
+                ret.push(c.code);
+            }
+        }
+        ret
+    }
+}
+
+/// E.g. `error_codes! { pub static REGISTRY; E0001: include_str!("E0001.md"), }`.
+#[macro_export]
+macro_rules! error_codes {
+    ($vis:vis static $registry:ident; $($code:ident: $explanation:expr),* $(,)?) => {
+        $(
+            #[allow(dead_code)]
+            $vis const $code: $crate::registry::ErrorCode = $crate::registry::ErrorCode {
+                code: stringify!($code),
+                explanation: $explanation,
+            };
+        )*
+        $vis static $registry: $crate::registry::Registry =
+            $crate::registry::Registry::new(&[$($code),*]);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    error_codes! {
+        static REGISTRY;
+        E0001: "# E0001\n\nAn expression was expected.\n",
+        E0002: "# E0002\n\nA token was unexpected.\n",
+    }
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(REGISTRY.codes().len(), 2);
+        assert_eq!(E0001.to_string(), "E0001");
+        assert_eq!(
+            REGISTRY.explain("E0002"),
+            Some("# E0002\n\nA token was unexpected.\n")
+        );
+        assert_eq!(REGISTRY.explain("E0003"), None);
+        assert!(REGISTRY.duplicates().is_empty());
+
+        let d1 = Diagnostic::error("e").with_code(E0001);
+        let d2 = Diagnostic::error("e").with_code("E0003");
+        let d3 = Diagnostic::error("e");
+        assert_eq!(d1.code.as_deref(), Some("E0001"));
+        assert_eq!(
+            REGISTRY.unregistered(vec![&d1, &d2, &d3, &d2]),
+            vec!["E0003"]
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        static CODES: [ErrorCode; 3] = [E0001, E0002, E0001];
+        assert_eq!(Registry::new(&CODES).duplicates(), vec!["E0001"]);
+    }
+}
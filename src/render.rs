@@ -0,0 +1,659 @@
+use std::collections::BTreeSet;
+
+use crate::chars::Chars;
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::line_index::LineIndex;
+use crate::source_map::{FileId, SourceMap};
+use crate::span::Span;
+
+// lines of a multi-line label shown before the rest are elided
+const MAX_MULTILINE: usize = 6;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    color: bool,
+    tab_width: usize,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer {
+            color: false,
+            tab_width: 4,
+        }
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Renderer::default()
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn render(&self, diag: &Diagnostic, source_map: &SourceMap) -> String {
+        let files = |id: FileId| {
+            let file = source_map.get(id)?;
+            Some(File {
+                name: file.name(),
+                chars: file.chars(),
+                lines: file.lines(),
+            })
+        };
+        let mut notes = vec![];
+        if let Some(span) = diag.span() {
+            for data in source_map.expansions().backtrace(span) {
+                let note = match source_map.lookup(data.call_site) {
+                    Some(loc) => format!("in this {} at {}", data.kind, loc),
+                    None => format!("in this {}", data.kind),
+                };
+                notes.push(note);
+            }
+        }
+        self.render_with(diag, &files, &notes)
+    }
+
+    pub fn render_chars(&self, diag: &Diagnostic, name: &str, chars: &Chars) -> String {
+        let lines = LineIndex::new(chars);
+        let files = |id: FileId| {
+            if id != chars.file() {
+                return None;
+            }
+            Some(File {
+                name,
+                chars,
+                lines: &lines,
+            })
+        };
+        self.render_with(diag, &files, &[])
+    }
+
+    pub fn render_all<'a>(
+        &self,
+        diags: impl IntoIterator<Item = &'a Diagnostic>,
+        source_map: &SourceMap,
+    ) -> String {
+        let ret: Vec<_> = diags
+            .into_iter()
+            .map(|d| self.render(d, source_map))
+            .collect();
+        ret.join("\n")
+    }
+
+    fn render_with<'a>(
+        &self,
+        diag: &Diagnostic,
+        files: &dyn Fn(FileId) -> Option<File<'a>>,
+        expn_notes: &[String],
+    ) -> String {
+        let mut out = String::new();
+        let severity = self.severity_style(diag.severity);
+        out.push_str(&self.paint(&diag.severity.to_string(), severity));
+        if let Some(code) = &diag.code {
+            out.push_str(&self.paint(&format!("[{}]", code), severity));
+        }
+        out.push_str(&self.paint(&format!(": {}", diag.message), BOLD));
+        out.push('\n');
+
+        // group labels by file, the file of the primary label first
+        let mut groups: Vec<(FileId, Vec<(&Label, bool)>)> = vec![];
+        let labels = diag.primary.iter().map(|l| (l, true));
+        let labels = labels.chain(diag.secondary.iter().map(|l| (l, false)));
+        for (label, primary) in labels {
+            let file = label.span.file();
+            match groups.iter_mut().find(|(f, _)| *f == file) {
+                Some((_, group)) => group.push((label, primary)),
+                None => groups.push((file, vec![(label, primary)])),
+            }
+        }
+        let snippets: Vec<_> = groups
+            .into_iter()
+            .filter_map(|(file, labels)| Some(Snippet::new(files(file)?, labels)))
+            .collect();
+
+        let width = snippets
+            .iter()
+            .map(|s| s.max_line().to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (i, snippet) in snippets.iter().enumerate() {
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            out.push_str(&format!(
+                "{}{} {}\n",
+                " ".repeat(width),
+                self.paint(arrow, BLUE),
+                snippet.location()
+            ));
+            self.render_snippet(&mut out, snippet, width, severity);
+        }
+
+        let footer = expn_notes.iter().chain(diag.notes.iter());
+        let suggestions = diag.suggestions.iter().map(|s| match &s.edits[..] {
+            [edit] => format!("{}: `{}`", s.message, edit.replacement),
+            _ => s.message.clone(),
+        });
+        let footer: Vec<_> = footer
+            .map(|n| ("note", n.clone()))
+            .chain(diag.help.iter().map(|h| ("help", h.clone())))
+            .chain(suggestions.map(|s| ("help", s)))
+            .collect();
+        if !snippets.is_empty() && !footer.is_empty() {
+            out.push_str(&self.paint(&format!("{} |", " ".repeat(width)), BLUE));
+            out.push('\n');
+        }
+        for (kind, text) in footer {
+            out.push_str(&format!(
+                "{} {} {}: {}\n",
+                " ".repeat(width),
+                self.paint("=", BLUE),
+                self.paint(kind, BOLD),
+                text
+            ));
+        }
+        out
+    }
+
+    fn render_snippet(
+        &self,
+        out: &mut String,
+        snippet: &Snippet,
+        width: usize,
+        severity: &'static str,
+    ) {
+        let gutter = |n: Option<usize>| {
+            let n = n.map(|n| n.to_string()).unwrap_or_default();
+            self.paint(&format!("{:>w$} |", n, w = width), BLUE)
+        };
+        out.push_str(&gutter(None));
+        out.push('\n');
+
+        let mut prev: Option<usize> = None;
+        for line in snippet.lines() {
+            if let Some(prev) = prev {
+                if line > prev + 1 {
+                    out.push_str(&self.paint("...", BLUE));
+                    out.push('\n');
+                }
+            }
+            prev = Some(line);
+
+            let text = self.expand(snippet.line_text(line));
+            let row = format!("{} {}", gutter(Some(line + 1)), text);
+            out.push_str(row.trim_end());
+            out.push('\n');
+
+            let marks = snippet.marks(line, self);
+            for row in self.annotation_rows(&marks, severity) {
+                let row = format!("{} {}", gutter(None), row);
+                out.push_str(row.trim_end());
+                out.push('\n');
+            }
+        }
+    }
+
+    // the underline row followed by rows for labels which did not fit inline
+    fn annotation_rows(&self, marks: &[Mark], severity: &'static str) -> Vec<String> {
+        if marks.is_empty() {
+            return vec![];
+        }
+        let mut underline = Row::default();
+        let mut marks: Vec<_> = marks.iter().collect();
+        // primary marks are drawn last so they win where labels overlap
+        marks.sort_by_key(|m| (m.primary, m.start));
+        for m in &marks {
+            let c = if m.primary { '^' } else { '-' };
+            for col in m.start..m.end {
+                underline.put(col, c, self.mark_style(m, severity));
+            }
+        }
+
+        let mut labeled: Vec<_> = marks.iter().filter(|m| m.label.is_some()).collect();
+        labeled.sort_by_key(|m| m.start);
+        let max_start = marks.iter().map(|m| m.start).max().unwrap();
+        let mut rows = vec![];
+        if let Some(last) = labeled.last() {
+            if last.start == max_start {
+                let col = underline.len() + 1;
+                let style = self.mark_style(last, severity);
+                underline.put_str(col, last.label.as_ref().unwrap(), style);
+                labeled.pop();
+            }
+        }
+        rows.push(underline.render(self));
+
+        while let Some(m) = labeled.pop() {
+            let mut connector = Row::default();
+            for d in labeled.iter().chain(std::iter::once(&m)) {
+                connector.put(d.start, '|', self.mark_style(d, severity));
+            }
+            rows.push(connector.render(self));
+
+            let mut message = Row::default();
+            for d in labeled.iter() {
+                message.put(d.start, '|', self.mark_style(d, severity));
+            }
+            let style = self.mark_style(m, severity);
+            message.put_str(m.start, m.label.as_ref().unwrap(), style);
+            rows.push(message.render(self));
+        }
+        rows
+    }
+
+    fn mark_style(&self, mark: &Mark, severity: &'static str) -> &'static str {
+        if mark.primary {
+            severity
+        } else {
+            BLUE
+        }
+    }
+
+    fn severity_style(&self, severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;32m",
+            Severity::Help => "\x1b[1;36m",
+        }
+    }
+
+    fn paint(&self, s: &str, style: &str) -> String {
+        if self.color && !s.is_empty() && !style.is_empty() {
+            format!("{}{}{}", style, s, RESET)
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn expand(&self, s: &str) -> String {
+        s.replace('\t', &" ".repeat(self.tab_width))
+    }
+
+    // display width of the first `col` chars of `s`
+    fn display_col(&self, s: &str, col: usize) -> usize {
+        s.chars().take(col).map(|c| self.char_width(c)).sum()
+    }
+
+    fn char_width(&self, c: char) -> usize {
+        if c == '\t' {
+            self.tab_width
+        } else {
+            char_width(c)
+        }
+    }
+}
+
+struct File<'a> {
+    name: &'a str,
+    chars: &'a Chars,
+    lines: &'a LineIndex,
+}
+
+struct Snippet<'a> {
+    file: File<'a>,
+    labels: Vec<(&'a Label, bool)>,
+    // zero based numbers of the lines shown
+    lines: BTreeSet<usize>,
+}
+
+// an underline in display columns
+struct Mark {
+    start: usize,
+    end: usize,
+    primary: bool,
+    label: Option<String>,
+}
+
+impl<'a> Snippet<'a> {
+    fn new(file: File<'a>, labels: Vec<(&'a Label, bool)>) -> Self {
+        let labels: Vec<_> = labels
+            .into_iter()
+            .filter(|(l, _)| file.lines.lookup_span(l.span).is_some())
+            .collect();
+        let mut lines = BTreeSet::new();
+        for (label, _) in &labels {
+            let (start, end) = Self::line_range(&file, label.span);
+            if end - start < MAX_MULTILINE {
+                for line in start..=end {
+                    lines.insert(line);
+                }
+            } else {
+                for line in [start, start + 1, end - 1, end] {
+                    lines.insert(line);
+                }
+            }
+        }
+        Snippet {
+            file,
+            labels,
+            lines,
+        }
+    }
+
+    // first and last line of `span`, a span ending right after a newline ends on that line
+    fn line_range(file: &File, span: Span) -> (usize, usize) {
+        let (start, end) = file.lines.lookup_span(span).unwrap();
+        if end.col == 0 && end.line > start.line {
+            (start.line, end.line - 1)
+        } else {
+            (start.line, end.line)
+        }
+    }
+
+    fn location(&self) -> String {
+        let span = self.labels.first().map(|(l, _)| l.span);
+        match span.and_then(|s| self.file.lines.line_col(s.start())) {
+            Some(start) => format!("{}:{}", self.file.name, start),
+            None => self.file.name.to_string(),
+        }
+    }
+
+    fn max_line(&self) -> usize {
+        self.lines.iter().last().map(|l| l + 1).unwrap_or(0)
+    }
+
+    fn lines(&self) -> Vec<usize> {
+        self.lines.iter().copied().collect()
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        let range = self.file.lines.line_range(line).unwrap();
+        let span = Span::new_in(self.file.chars.file(), range.start, range.end);
+        self.file.chars.get_str(span).unwrap()
+    }
+
+    fn marks(&self, line: usize, renderer: &Renderer) -> Vec<Mark> {
+        let text = self.line_text(line);
+        let line_width = renderer.display_col(text, usize::MAX);
+        let mut marks = vec![];
+        for (label, primary) in &self.labels {
+            let (start_line, end_line) = Self::line_range(&self.file, label.span);
+            let (start, end) = self.file.lines.lookup_span(label.span).unwrap();
+            let message = Some(label.message.clone()).filter(|m| !m.is_empty());
+            let (s, e, label) = if start_line == line && end_line == line {
+                let s = renderer.display_col(text, start.col);
+                let e = renderer.display_col(text, end.col.max(start.col));
+                (s, e, message)
+            } else if start_line == line {
+                let s = renderer.display_col(text, start.col);
+                (s, line_width, None)
+            } else if end_line == line {
+                let end_col = if end.line == line {
+                    end.col
+                } else {
+                    text.chars().count()
+                };
+                let indent = text.chars().take_while(|c| c.is_whitespace()).count();
+                let s = renderer.display_col(text, indent.min(end_col));
+                let e = renderer.display_col(text, end_col);
+                (s, e, message)
+            } else {
+                continue;
+            };
+            marks.push(Mark {
+                start: s,
+                end: e.max(s + 1),
+                primary: *primary,
+                label,
+            });
+        }
+        marks
+    }
+}
+
+#[derive(Default)]
+struct Row {
+    cells: Vec<(char, &'static str)>,
+}
+
+impl Row {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn put(&mut self, col: usize, c: char, style: &'static str) {
+        if self.cells.len() <= col {
+            self.cells.resize(col + 1, (' ', ""));
+        }
+        self.cells[col] = (c, style);
+    }
+
+    fn put_str(&mut self, col: usize, s: &str, style: &'static str) {
+        for (i, c) in s.chars().enumerate() {
+            self.put(col + i, c, style);
+        }
+    }
+
+    fn render(&self, renderer: &Renderer) -> String {
+        let mut ret = String::new();
+        let mut i = 0;
+        while i < self.cells.len() {
+            let style = self.cells[i].1;
+            let mut run = String::new();
+            while i < self.cells.len() && self.cells[i].1 == style {
+                run.push(self.cells[i].0);
+                i += 1;
+            }
+            ret.push_str(&renderer.paint(&run, style));
+        }
+        ret
+    }
+}
+
+/// 0 for combining marks, 2 for wide east asian chars and emoji, 1 otherwise.
+pub fn char_width(c: char) -> usize {
+    let c = c as u32;
+    const ZERO: &[(u32, u32)] = &[
+        (0x0300, 0x036F),
+        (0x0483, 0x0489),
+        (0x0591, 0x05BD),
+        (0x0610, 0x061A),
+        (0x064B, 0x065F),
+        (0x0E31, 0x0E31),
+        (0x0E34, 0x0E3A),
+        (0x1AB0, 0x1AFF),
+        (0x1DC0, 0x1DFF),
+        (0x200B, 0x200F),
+        (0x20D0, 0x20FF),
+        (0xFE00, 0xFE0F),
+        (0xFE20, 0xFE2F),
+    ];
+    const WIDE: &[(u32, u32)] = &[
+        (0x1100, 0x115F),
+        (0x2E80, 0x303E),
+        (0x3041, 0x33FF),
+        (0x3400, 0x4DBF),
+        (0x4E00, 0x9FFF),
+        (0xA000, 0xA4CF),
+        (0xAC00, 0xD7A3),
+        (0xF900, 0xFAFF),
+        (0xFE30, 0xFE4F),
+        (0xFF00, 0xFF60),
+        (0xFFE0, 0xFFE6),
+        (0x1F300, 0x1F64F),
+        (0x1F900, 0x1F9FF),
+        (0x20000, 0x2FFFD),
+        (0x30000, 0x3FFFD),
+    ];
+    let within = |table: &[(u32, u32)]| table.iter().any(|(lo, hi)| *lo <= c && c <= *hi);
+    if within(ZERO) {
+        0
+    } else if within(WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Applicability;
+    use crate::expn::{ExpnData, ExpnKind};
+
+    fn source_map() -> (SourceMap, FileId, FileId) {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file(
+            "main.x",
+            "let x = 1 +;\nfoo(\n  a,\n  b\n)\n\tlet 中文 = y;\n",
+        );
+        let b = sm.add_file("lib.x", "fn foo(a, b)\n");
+        (sm, a, b)
+    }
+
+    #[test]
+    fn test_render() {
+        let (sm, a, _) = source_map();
+        let d = Diagnostic::error("expected expression, found `;`")
+            .with_code("E0001")
+            .with_primary(Span::new_in(a, 11, 12), "expected expression")
+            .with_label(Span::new_in(a, 8, 11), "this operator")
+            .with_label(Span::new_in(a, 4, 5), "binding")
+            .with_note("a note")
+            .with_help("add an operand")
+            .with_replacement(
+                Span::new_in(a, 11, 11),
+                "try adding a literal",
+                " 2",
+                Applicability::HasPlaceholders,
+            );
+        let expected = "\
+error[E0001]: expected expression, found `;`
+ --> main.x:1:12
+  |
+1 | let x = 1 +;
+  |     -   ---^ expected expression
+  |     |   |
+  |     |   this operator
+  |     |
+  |     binding
+  |
+  = note: a note
+  = help: add an operand
+  = help: try adding a literal: ` 2`
+";
+        assert_eq!(Renderer::new().render(&d, &sm), expected);
+    }
+
+    #[test]
+    fn test_render_multi_file() {
+        let (sm, a, b) = source_map();
+        let d = Diagnostic::warning("wrong call")
+            .with_primary(Span::new_in(a, 13, 27), "this call")
+            .with_label(Span::new_in(b, 3, 6), "defined here");
+        let expected = "\
+warning: wrong call
+ --> main.x:2:1
+  |
+2 | foo(
+  | ^^^^
+3 |   a,
+4 |   b
+  |   ^ this call
+ ::: lib.x:1:4
+  |
+1 | fn foo(a, b)
+  |    --- defined here
+";
+        assert_eq!(Renderer::new().render(&d, &sm), expected);
+    }
+
+    #[test]
+    fn test_render_width() {
+        let (sm, a, _) = source_map();
+        let d = Diagnostic::error("wide")
+            .with_primary(Span::new_in(a, 34, 36), "wide")
+            .with_label(Span::new_in(a, 39, 40), "");
+        let expected = "\
+error: wide
+ --> main.x:6:6
+  |
+6 |     let 中文 = y;
+  |         ^^^^   -
+  |         |
+  |         wide
+";
+        assert_eq!(Renderer::new().render(&d, &sm), expected);
+        let expected = "6 |   let 中文 = y;";
+        let out = Renderer::new().tab_width(2).render(&d, &sm);
+        assert_eq!(out.lines().nth(3).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_eof_and_gap() {
+        let (sm, a, _) = source_map();
+        let d = Diagnostic::error("eof")
+            .with_primary(Span::point_in(a, 42), "here")
+            .with_label(Span::new_in(a, 0, 3), "start");
+        let expected = "\
+error: eof
+ --> main.x:7:1
+  |
+1 | let x = 1 +;
+  | --- start
+...
+7 |
+  | ^ here
+";
+        assert_eq!(Renderer::new().render(&d, &sm), expected);
+    }
+
+    #[test]
+    fn test_render_color() {
+        let (sm, a, _) = source_map();
+        let d = Diagnostic::error("e").with_primary(Span::new_in(a, 0, 3), "l");
+        let out = Renderer::new().color(true).render(&d, &sm);
+        assert!(out.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: e\x1b[0m\n"));
+        assert!(out.contains("\x1b[1;31m^^^\x1b[0m \x1b[1;31ml\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_expansion() {
+        let (mut sm, a, _) = source_map();
+        let call = Span::new_in(a, 13, 16);
+        let expn = sm.register_expansion(ExpnData::new(ExpnKind::Macro("foo".into()), call));
+        let d = Diagnostic::error("e").with_primary(Span::new_in(a, 0, 3).with_expn(expn), "l");
+        let out = Renderer::new().render(&d, &sm);
+        let note = "  = note: in this expansion of macro `foo` at main.x:2:1\n";
+        assert!(out.ends_with(note));
+    }
+
+    #[test]
+    fn test_render_chars() {
+        let chars = Chars::new("a+b");
+        let d = Diagnostic::error("e")
+            .with_primary(Span::new(1, 2), "op")
+            .with_note("n");
+        let expected = "\
+error: e
+ --> x:1:2
+  |
+1 | a+b
+  |  ^ op
+  |
+  = note: n
+";
+        assert_eq!(Renderer::new().render_chars(&d, "x", &chars), expected);
+        let d = Diagnostic::error("e");
+        assert_eq!(Renderer::new().render_chars(&d, "x", &chars), "error: e\n");
+    }
+
+    #[test]
+    fn test_char_width() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('中'), 2);
+        assert_eq!(char_width('😀'), 2);
+        assert_eq!(char_width('\u{301}'), 0);
+    }
+}
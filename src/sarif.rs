@@ -0,0 +1,324 @@
+use std::fmt::Write;
+
+use crate::diagnostic::{Diagnostic, Label, Severity, Suggestion};
+use crate::json::Json;
+use crate::source_map::{FileId, SourceMap};
+use crate::span::Span;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Columns and char offsets count chars, like spans.
+#[derive(Clone, Debug)]
+pub struct SarifWriter {
+    name: String,
+    version: Option<String>,
+    information_uri: Option<String>,
+}
+
+impl SarifWriter {
+    pub fn new(tool_name: &str) -> Self {
+        SarifWriter {
+            name: tool_name.to_string(),
+            version: None,
+            information_uri: None,
+        }
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    pub fn information_uri(mut self, uri: &str) -> Self {
+        self.information_uri = Some(uri.to_string());
+        self
+    }
+
+    pub fn write<'a>(
+        &self,
+        diags: impl IntoIterator<Item = &'a Diagnostic>,
+        source_map: &SourceMap,
+    ) -> String {
+        let diags: Vec<_> = diags.into_iter().collect();
+
+        let mut rules: Vec<&str> = vec![];
+        for code in diags.iter().filter_map(|d| d.code.as_deref()) {
+            if !rules.contains(&code) {
+                rules.push(code);
+            }
+        }
+        let mut artifacts: Vec<FileId> = vec![];
+        for diag in &diags {
+            let labels = diag.primary.iter().chain(diag.secondary.iter());
+            let edits = diag.suggestions.iter().flat_map(|s| s.edits.iter());
+            let spans = labels.map(|l| l.span).chain(edits.map(|e| e.span));
+            for file in spans.map(|s| s.file()) {
+                if source_map.get(file).is_some() && !artifacts.contains(&file) {
+                    artifacts.push(file);
+                }
+            }
+        }
+
+        let ctx = Ctx {
+            source_map,
+            rules: &rules,
+            artifacts: &artifacts,
+        };
+        let results = diags.iter().map(|d| ctx.result(d)).collect();
+        let run = Json::Obj(vec![
+            ("tool", Json::Obj(vec![("driver", self.driver(&rules))])),
+            (
+                "artifacts",
+                Json::Arr(artifacts.iter().map(|f| ctx.artifact(*f)).collect()),
+            ),
+            ("columnKind", Json::str("unicodeCodePoints")),
+            ("results", Json::Arr(results)),
+        ]);
+        let log = Json::Obj(vec![
+            ("$schema", Json::str(SCHEMA)),
+            ("version", Json::str("2.1.0")),
+            ("runs", Json::Arr(vec![run])),
+        ]);
+        log.to_string()
+    }
+
+    fn driver(&self, rules: &[&str]) -> Json {
+        let rules = rules
+            .iter()
+            .map(|id| Json::Obj(vec![("id", Json::str(*id))]))
+            .collect();
+        let mut fields = vec![("name", Json::str(self.name.clone()))];
+        if let Some(version) = &self.version {
+            fields.push(("version", Json::str(version.clone())));
+        }
+        if let Some(uri) = &self.information_uri {
+            fields.push(("informationUri", Json::str(uri.clone())));
+        }
+        fields.push(("rules", Json::Arr(rules)));
+        Json::Obj(fields)
+    }
+}
+
+struct Ctx<'a> {
+    source_map: &'a SourceMap,
+    rules: &'a [&'a str],
+    artifacts: &'a [FileId],
+}
+
+impl Ctx<'_> {
+    fn result(&self, diag: &Diagnostic) -> Json {
+        let level = match diag.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "note",
+        };
+        let mut fields = vec![];
+        if let Some(code) = diag.code.as_deref() {
+            let index = self.rules.iter().position(|r| *r == code).unwrap();
+            fields.push(("ruleId", Json::str(code)));
+            fields.push(("ruleIndex", Json::Num(index)));
+        }
+        fields.push(("level", Json::str(level)));
+        fields.push(("message", message(&diag.message)));
+
+        let locations = diag.primary.iter().filter_map(|l| self.location(l, None));
+        fields.push(("locations", Json::Arr(locations.collect())));
+        let related = diag
+            .secondary
+            .iter()
+            .enumerate()
+            .filter_map(|(i, l)| self.location(l, Some(i)));
+        fields.push(("relatedLocations", Json::Arr(related.collect())));
+        let fixes: Vec<_> = diag
+            .suggestions
+            .iter()
+            .filter_map(|s| self.fix(s))
+            .collect();
+        if !fixes.is_empty() {
+            fields.push(("fixes", Json::Arr(fixes)));
+        }
+
+        if !diag.notes.is_empty() || !diag.help.is_empty() {
+            let strings = |v: &[String]| Json::Arr(v.iter().map(Json::str).collect());
+            let properties = vec![
+                ("notes", strings(&diag.notes)),
+                ("help", strings(&diag.help)),
+            ];
+            fields.push(("properties", Json::Obj(properties)));
+        }
+        Json::Obj(fields)
+    }
+
+    fn location(&self, label: &Label, id: Option<usize>) -> Option<Json> {
+        let mut fields = vec![];
+        if let Some(id) = id {
+            fields.push(("id", Json::Num(id)));
+        }
+        fields.push(("physicalLocation", self.physical_location(label.span)?));
+        if !label.message.is_empty() {
+            fields.push(("message", message(&label.message)));
+        }
+        Some(Json::Obj(fields))
+    }
+
+    // edits grouped by file, in the order the files first appear
+    fn fix(&self, suggestion: &Suggestion) -> Option<Json> {
+        let mut changes: Vec<(FileId, Vec<Json>)> = vec![];
+        for edit in &suggestion.edits {
+            let Some(region) = self.region(edit.span) else {
+                continue;
+            };
+            let inserted = Json::Obj(vec![("text", Json::str(edit.replacement.clone()))]);
+            let replacement = Json::Obj(vec![
+                ("deletedRegion", region),
+                ("insertedContent", inserted),
+            ]);
+            let file = edit.span.file();
+            match changes.iter_mut().find(|(f, _)| *f == file) {
+                Some((_, replacements)) => replacements.push(replacement),
+                None => changes.push((file, vec![replacement])),
+            }
+        }
+        if changes.is_empty() {
+            return None;
+        }
+        let changes = changes.into_iter().map(|(file, replacements)| {
+            Json::Obj(vec![
+                ("artifactLocation", self.artifact_location(file)),
+                ("replacements", Json::Arr(replacements)),
+            ])
+        });
+        let applicability = suggestion.applicability.to_string();
+        Some(Json::Obj(vec![
+            ("description", message(&suggestion.message)),
+            ("artifactChanges", Json::Arr(changes.collect())),
+            (
+                "properties",
+                Json::Obj(vec![("applicability", Json::str(applicability))]),
+            ),
+        ]))
+    }
+
+    fn physical_location(&self, span: Span) -> Option<Json> {
+        let region = self.region(span)?;
+        Some(Json::Obj(vec![
+            ("artifactLocation", self.artifact_location(span.file())),
+            ("region", region),
+        ]))
+    }
+
+    fn region(&self, span: Span) -> Option<Json> {
+        let file = self.source_map.get(span.file())?;
+        let (start, end) = file.lines().lookup_span(span)?;
+        let bytes = file.original_range(span)?;
+        Some(Json::Obj(vec![
+            ("startLine", Json::Num(start.line + 1)),
+            ("startColumn", Json::Num(start.col + 1)),
+            ("endLine", Json::Num(end.line + 1)),
+            ("endColumn", Json::Num(end.col + 1)),
+            ("charOffset", Json::Num(span.start())),
+            ("charLength", Json::Num(span.len())),
+            ("byteOffset", Json::Num(bytes.start)),
+            ("byteLength", Json::Num(bytes.len())),
+        ]))
+    }
+
+    fn artifact_location(&self, file: FileId) -> Json {
+        let index = self.artifacts.iter().position(|f| *f == file).unwrap();
+        let name = self.source_map.get(file).unwrap().name();
+        Json::Obj(vec![
+            ("uri", Json::Str(uri(name))),
+            ("index", Json::Num(index)),
+        ])
+    }
+
+    fn artifact(&self, file: FileId) -> Json {
+        let name = self.source_map.get(file).unwrap().name();
+        let location = Json::Obj(vec![("uri", Json::Str(uri(name)))]);
+        Json::Obj(vec![("location", location)])
+    }
+}
+
+// a relative uri reference for a file name, everything but unreserved chars and `/` is
+// percent-encoded
+fn uri(name: &str) -> String {
+    let mut ret = String::new();
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                ret.push(b as char)
+            }
+            _ => write!(ret, "%{:02X}", b).unwrap(),
+        }
+    }
+    ret
+}
+
+fn message(text: &str) -> Json {
+    Json::Obj(vec![("text", Json::str(text))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Applicability;
+
+    #[test]
+    fn test_write() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("main.x", "a\nbé+");
+        let d = Diagnostic::error("unexpected `+`")
+            .with_code("E0001")
+            .with_primary(Span::new_in(a, 4, 5), "");
+        let out = SarifWriter::new("reacto").write(vec![&d], &sm);
+        let expected = concat!(
+            r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","#,
+            r#""runs":[{"tool":{"driver":{"name":"reacto","rules":[{"id":"E0001"}]}},"#,
+            r#""artifacts":[{"location":{"uri":"main.x"}}],"columnKind":"unicodeCodePoints","#,
+            r#""results":[{"ruleId":"E0001","ruleIndex":0,"level":"error","#,
+            r#""message":{"text":"unexpected `+`"},"locations":[{"physicalLocation":{"#,
+            r#""artifactLocation":{"uri":"main.x","index":0},"region":{"startLine":2,"#,
+            r#""startColumn":3,"endLine":2,"endColumn":4,"charOffset":4,"charLength":1,"#,
+            r#""byteOffset":5,"byteLength":1}}}],"relatedLocations":[]}]}]}"#,
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_write_fix() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("main.x", "f(a b)");
+        let b = sm.add_file("lib.x", "");
+        let d = Diagnostic::error("expected `,`").with_suggestion(
+            Suggestion::new("add a comma", Applicability::MachineApplicable)
+                .with_edit(Span::new_in(a, 3, 3), ",")
+                .with_edit(Span::new_in(b, 0, 0), "x"),
+        );
+        let out = SarifWriter::new("reacto").write(vec![&d], &sm);
+        let expected = concat!(
+            r#""fixes":[{"description":{"text":"add a comma"},"artifactChanges":[{"#,
+            r#""artifactLocation":{"uri":"main.x","index":0},"replacements":[{"deletedRegion":"#,
+            r#"{"startLine":1,"startColumn":4,"endLine":1,"endColumn":4,"charOffset":3,"#,
+            r#""charLength":0,"byteOffset":3,"byteLength":0},"insertedContent":{"text":","}}]},"#,
+            r#"{"artifactLocation":{"uri":"lib.x","index":1},"replacements":[{"deletedRegion":"#,
+        );
+        assert!(out.contains(expected));
+        assert!(out.contains(r#""properties":{"applicability":"MachineApplicable"}"#));
+    }
+
+    #[test]
+    fn test_uri() {
+        assert_eq!(uri("src/main.x"), "src/main.x");
+        assert_eq!(uri("my dir/a#1%.x"), "my%20dir/a%231%25.x");
+        assert_eq!(uri(r"C:\é.x"), "C%3A%5C%C3%A9.x");
+    }
+
+    #[test]
+    fn test_write_empty() {
+        let sm = SourceMap::new();
+        let d = Diagnostic::note("n").with_primary(Span::new(0, 1), "");
+        let out = SarifWriter::new("t").write(vec![&d], &sm);
+        assert!(out.contains(r#""level":"note","message":{"text":"n"},"locations":[]"#));
+        assert!(out.contains(r#""artifacts":[]"#));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::diagnostic::{Diagnostic, Severity};
+
+/// Everything emitted after the error limit is reached is dropped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ErrorLimitReached {
+    pub limit: usize,
+}
+
+impl fmt::Display for ErrorLimitReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "aborting after {} errors", self.limit)
+    }
+}
+
+impl std::error::Error for ErrorLimitReached {}
+
+/// Clones share the same diagnostics.
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticSink {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    diags: Vec<Diagnostic>,
+    seen: HashSet<Diagnostic>,
+    errors: usize,
+    warnings: usize,
+    error_limit: Option<usize>,
+    warnings_as_errors: bool,
+}
+
+impl DiagnosticSink {
+    pub fn new() -> Self {
+        DiagnosticSink::default()
+    }
+
+    pub fn error_limit(self, limit: usize) -> Self {
+        self.lock().error_limit = Some(limit);
+        self
+    }
+
+    pub fn warnings_as_errors(self, yes: bool) -> Self {
+        self.lock().warnings_as_errors = yes;
+        self
+    }
+
+    /// Identical diagnostics are recorded once.
+    pub fn emit(&self, mut diag: Diagnostic) -> Result<(), ErrorLimitReached> {
+        let mut inner = self.lock();
+        inner.check_limit()?;
+        if inner.warnings_as_errors && diag.severity == Severity::Warning {
+            diag.severity = Severity::Error;
+        }
+        if inner.seen.contains(&diag) {
+            return Ok(());
+        }
+        match diag.severity {
+            Severity::Error => inner.errors += 1,
+            Severity::Warning => inner.warnings += 1,
+            Severity::Note | Severity::Help => {}
+        }
+        inner.seen.insert(diag.clone());
+        inner.diags.push(diag);
+        inner.check_limit()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.lock().errors
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.lock().warnings
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn limit_reached(&self) -> bool {
+        self.lock().check_limit().is_err()
+    }
+
+    /// Sorted by file and span, those without a span last.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut ret = self.lock().diags.clone();
+        sort(&mut ret);
+        ret
+    }
+
+    /// Empties the sink, counts are kept.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        let mut inner = self.lock();
+        inner.seen.clear();
+        let mut ret = std::mem::take(&mut inner.diags);
+        sort(&mut ret);
+        ret
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl Inner {
+    fn check_limit(&self) -> Result<(), ErrorLimitReached> {
+        match self.error_limit {
+            Some(limit) if self.errors >= limit => Err(ErrorLimitReached { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+// stable, so diagnostics at the same span keep their order
+fn sort(diags: &mut [Diagnostic]) {
+    diags.sort_by_key(|d| match d.span() {
+        Some(span) => (false, span.file(), span.start(), span.end()),
+        None => (true, Default::default(), 0, 0),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::FileId;
+    use crate::span::Span;
+
+    #[test]
+    fn test_counts_and_dedupe() {
+        let sink = DiagnosticSink::new();
+        let e = Diagnostic::error("e").with_primary(Span::new(0, 1), "");
+        sink.emit(e.clone()).unwrap();
+        sink.emit(e).unwrap();
+        sink.emit(Diagnostic::warning("w")).unwrap();
+        sink.emit(Diagnostic::note("n")).unwrap();
+        assert_eq!(sink.error_count(), 1);
+        assert_eq!(sink.warning_count(), 1);
+        assert!(sink.has_errors());
+        assert_eq!(sink.diagnostics().len(), 3);
+    }
+
+    #[test]
+    fn test_limit() {
+        let sink = DiagnosticSink::new().error_limit(2);
+        assert_eq!(sink.emit(Diagnostic::error("a")), Ok(()));
+        assert_eq!(sink.emit(Diagnostic::warning("w")), Ok(()));
+        assert!(!sink.limit_reached());
+        assert_eq!(
+            sink.emit(Diagnostic::error("b")),
+            Err(ErrorLimitReached { limit: 2 })
+        );
+        assert!(sink.emit(Diagnostic::error("c")).is_err());
+        assert!(sink.limit_reached());
+        assert_eq!(sink.error_count(), 2);
+        assert_eq!(sink.diagnostics().len(), 3);
+    }
+
+    #[test]
+    fn test_warnings_as_errors() {
+        let sink = DiagnosticSink::new().warnings_as_errors(true);
+        sink.emit(Diagnostic::warning("w")).unwrap();
+        sink.emit(Diagnostic::error("w")).unwrap();
+        assert_eq!(sink.error_count(), 1);
+        assert_eq!(sink.warning_count(), 0);
+        assert_eq!(sink.diagnostics()[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_sorted_and_shared() {
+        let sink = DiagnosticSink::new();
+        let other = sink.clone();
+        let at = |file, start, msg: &str| {
+            Diagnostic::error(msg).with_primary(Span::new_in(FileId(file), start, start + 1), "")
+        };
+        sink.emit(Diagnostic::error("none")).unwrap();
+        sink.emit(at(1, 0, "b0")).unwrap();
+        other.emit(at(0, 5, "a5")).unwrap();
+        other.emit(at(0, 2, "a2")).unwrap();
+        let messages: Vec<_> = sink.take().into_iter().map(|d| d.message).collect();
+        assert_eq!(messages, vec!["a2", "a5", "b0", "none"]);
+        assert!(other.diagnostics().is_empty());
+        assert_eq!(other.error_count(), 4);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::chars::Chars;
+use crate::expn::{ExpnData, ExpnId, ExpnTable};
+use crate::line_index::{LineCol, LineIndex};
+use crate::load::{self, Encoding, LoadError, LoadOptions, Loaded};
+use crate::span::Span;
+
+/// Text outside of a `SourceMap` has `FileId::NONE`, which no file of a `SourceMap` has.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct FileId(pub(crate) usize);
+
+impl FileId {
+    pub const NONE: FileId = FileId(usize::MAX);
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == FileId::NONE
+    }
+}
+
+impl Default for FileId {
+    fn default() -> Self {
+        FileId::NONE
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    id: FileId,
+    name: String,
+    chars: Chars,
+    lines: LineIndex,
+    // set for files loaded from raw bytes
+    loaded: Option<Loaded>,
+}
+
+impl SourceFile {
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn chars(&self) -> &Chars {
+        &self.chars
+    }
+
+    pub fn lines(&self) -> &LineIndex {
+        &self.lines
+    }
+
+    /// `None` for text added as a string.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.loaded.as_ref().map(|l| l.encoding)
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.loaded.as_ref().is_some_and(|l| l.bom)
+    }
+
+    /// Byte range in the input the file was created from.
+    pub fn original_range(&self, span: Span) -> Option<Range<usize>> {
+        if span.file() != self.id {
+            return None;
+        }
+        match &self.loaded {
+            Some(loaded) => loaded.offsets.original_range(span),
+            None => {
+                let start = self.chars.byte_offset(span.start())?;
+                let end = self.chars.byte_offset(span.end())?;
+                Some(start..end)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub file: FileId,
+    pub name: String,
+    pub start: LineCol,
+    pub end: LineCol,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.start)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    expansions: ExpnTable,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap {
+            files: vec![],
+            expansions: ExpnTable::new(),
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, input: impl Into<String>) -> FileId {
+        let id = self.next_id();
+        let chars = Chars::from_string_in(id, input.into());
+        self.push(name, chars, None)
+    }
+
+    pub fn load_file(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        options: &LoadOptions,
+    ) -> Result<FileId, LoadError> {
+        let loaded = load::load_in(self.next_id(), bytes, options)?;
+        Ok(self.push(name, loaded.chars.clone(), Some(loaded)))
+    }
+
+    fn next_id(&self) -> FileId {
+        FileId(self.files.len())
+    }
+
+    fn push(&mut self, name: &str, chars: Chars, loaded: Option<Loaded>) -> FileId {
+        let id = chars.file();
+        let lines = LineIndex::new(&chars);
+        self.files.push(SourceFile {
+            id,
+            name: name.to_string(),
+            chars,
+            lines,
+            loaded,
+        });
+        id
+    }
+
+    pub fn get(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.0)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&SourceFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn chars(&self, id: FileId) -> Option<&Chars> {
+        self.get(id).map(|f| f.chars())
+    }
+
+    pub fn get_string(&self, span: Span) -> Option<String> {
+        self.get(span.file())?.chars.get_string(span)
+    }
+
+    pub fn expansions(&self) -> &ExpnTable {
+        &self.expansions
+    }
+
+    pub fn register_expansion(&mut self, data: ExpnData) -> ExpnId {
+        self.expansions.register(data)
+    }
+
+    /// Location of the user written text.
+    pub fn lookup_source(&self, span: Span) -> Option<Location> {
+        self.lookup(self.expansions.source_span(span))
+    }
+
+    pub fn lookup(&self, span: Span) -> Option<Location> {
+        let file = self.get(span.file())?;
+        let (start, end) = file.lines.lookup_span(span)?;
+        Some(Location {
+            file: file.id,
+            name: file.name.clone(),
+            start,
+            end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expn::ExpnKind;
+
+    #[test]
+    fn test_add_file() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.txt", "ab");
+        let b = sm.add_file("b.txt", "cd\nef");
+        assert_eq!(a.get(), 0);
+        assert_eq!(b.get(), 1);
+        assert!(!a.is_none());
+        assert!(FileId::default().is_none());
+        assert!(sm.get(FileId::NONE).is_none());
+        // spans made without a file are not in the first one
+        assert!(sm.lookup(Span::new(0, 1)).is_none());
+        assert_eq!(sm.files().len(), 2);
+        assert_eq!(sm.get(b).unwrap().name(), "b.txt");
+        assert_eq!(sm.find("a.txt").unwrap().id(), a);
+        assert!(sm.find("c.txt").is_none());
+        assert_eq!(sm.chars(a).unwrap(), "ab");
+        assert_eq!(sm.chars(b).unwrap().file(), b);
+    }
+
+    #[test]
+    fn test_load_file() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.txt", "é");
+        let options = LoadOptions::new().normalize_newlines(true);
+        let b = sm
+            .load_file("b.txt", b"\xEF\xBB\xBFa\r\nb", &options)
+            .unwrap();
+        assert_eq!(sm.chars(b).unwrap(), "a\nb");
+        let file = sm.get(b).unwrap();
+        assert_eq!(file.original_range(Span::new_in(b, 2, 3)), Some(6..7));
+        assert_eq!(file.original_range(Span::new_in(a, 0, 1)), None);
+        assert_eq!(file.encoding(), Some(Encoding::Utf8));
+        assert!(file.has_bom());
+        let file = sm.get(a).unwrap();
+        assert_eq!(file.original_range(Span::new_in(a, 0, 1)), Some(0..2));
+        assert_eq!(file.encoding(), None);
+        assert!(!file.has_bom());
+        assert!(sm.load_file("c.txt", b"\xFF", &LoadOptions::new()).is_err());
+        assert_eq!(sm.files().len(), 2);
+    }
+
+    #[test]
+    fn test_get_string() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.txt", "ab");
+        let b = sm.add_file("b.txt", "cd");
+        assert_eq!(sm.get_string(Span::new_in(a, 0, 1)).unwrap(), "a");
+        assert_eq!(sm.get_string(Span::new_in(b, 0, 1)).unwrap(), "c");
+        assert_eq!(sm.get_string(Span::new_in(b, 0, 3)), None);
+        assert_eq!(sm.get_string(Span::new_in(FileId(2), 0, 1)), None);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut sm = SourceMap::new();
+        sm.add_file("a.txt", "ab");
+        let b = sm.add_file("b.txt", "cd\nef");
+        let loc = sm.lookup(Span::new_in(b, 3, 5)).unwrap();
+        assert_eq!(loc.file, b);
+        assert_eq!(loc.start, LineCol { line: 1, col: 0 });
+        assert_eq!(loc.end, LineCol { line: 1, col: 2 });
+        assert_eq!(loc.to_string(), "b.txt:2:1");
+    }
+
+    #[test]
+    fn test_lookup_source() {
+        let mut sm = SourceMap::new();
+        let a = sm.add_file("a.txt", "ab\nm!(x)");
+        let call = Span::new_in(a, 3, 8);
+        let expn = sm.register_expansion(ExpnData::new(ExpnKind::Macro("m".into()), call));
+        let generated = Span::new_in(a, 6, 7).with_expn(expn);
+        assert_eq!(sm.lookup(generated).unwrap().to_string(), "a.txt:2:4");
+        assert_eq!(
+            sm.lookup_source(generated).unwrap().to_string(),
+            "a.txt:2:1"
+        );
+        assert_eq!(sm.expansions().backtrace(generated).len(), 1);
+    }
+}
@@ -1,10 +1,17 @@
+use std::ops::Range;
+
+use crate::expn::ExpnId;
+use crate::source_map::FileId;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Span {
+    file: FileId,
     start: usize,
     end: usize,
+    expn: ExpnId,
 }
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Eq, PartialEq, Hash)]
 pub struct S<T> {
     pub span: Span,
     pub tok: T,
@@ -12,8 +19,42 @@ pub struct S<T> {
 
 impl Span {
     pub fn new(start: usize, end: usize) -> Self {
-        assert!(start < end, "start must less then end");
-        Span { start, end }
+        Self::new_in(FileId::default(), start, end)
+    }
+
+    pub fn new_in(file: FileId, start: usize, end: usize) -> Self {
+        assert!(start <= end, "start must not be greater than end");
+        Span {
+            file,
+            start,
+            end,
+            expn: ExpnId::root(),
+        }
+    }
+
+    // same file and expansion, different offsets
+    fn with_range(&self, start: usize, end: usize) -> Span {
+        Self::new_in(self.file, start, end).with_expn(self.expn)
+    }
+
+    pub fn point(pos: usize) -> Self {
+        Self::new(pos, pos)
+    }
+
+    pub fn point_in(file: FileId, pos: usize) -> Self {
+        Self::new_in(file, pos, pos)
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn expn(&self) -> ExpnId {
+        self.expn
+    }
+
+    pub fn with_expn(&self, expn: ExpnId) -> Span {
+        Span { expn, ..*self }
     }
 
     pub fn start(&self) -> usize {
@@ -28,13 +69,189 @@ impl Span {
         self.end - self.start
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Panics if the spans are in different files.
     pub fn merge(&self, other: Span) -> Span {
+        assert_eq!(
+            self.file, other.file,
+            "can not merge spans of different files"
+        );
         let start = self.start.min(other.start);
         let end = self.end.max(other.end);
-        Self::new(start, end)
+        self.with_range(start, end)
+    }
+
+    pub fn contains(&self, other: Span) -> bool {
+        self.file == other.file && self.start <= other.start && other.end <= self.end
+    }
+
+    pub fn contains_pos(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// The common part of two spans, empty if they only touch, `None` if they are disjoint.
+    pub fn intersect(&self, other: Span) -> Option<Span> {
+        if self.file != other.file {
+            return None;
+        }
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        if start <= end {
+            Some(self.with_range(start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Empty if they touch or overlap.
+    pub fn between(&self, other: Span) -> Span {
+        assert_eq!(self.file, other.file, "spans of different files");
+        let (a, b) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (&other, *self)
+        };
+        let start = a.end.min(b.start);
+        self.with_range(start, b.start)
+    }
+
+    pub fn shrink(&self, left: usize, right: usize) -> Span {
+        assert!(
+            left + right <= self.len(),
+            "shrink more than the span length"
+        );
+        self.with_range(self.start + left, self.end - right)
+    }
+
+    pub fn shift(&self, offset: isize) -> Span {
+        let shift = |pos: usize| {
+            let ret = pos as isize + offset;
+            assert!(ret >= 0, "shift before the start of the source");
+            ret as usize
+        };
+        self.with_range(shift(self.start), shift(self.end))
+    }
+
+    /// `None` if `base` does not contain `self`.
+    pub fn relative_to(&self, base: Span) -> Option<Span> {
+        if base.contains(*self) {
+            Some(self.shift(-(base.start as isize)))
+        } else {
+            None
+        }
+    }
+
+    pub fn subspan(&self, start: usize, end: usize) -> Option<Span> {
+        if start <= end && end <= self.len() {
+            Some(self.with_range(self.start + start, self.start + end))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn from_range_in(file: FileId, range: Range<usize>) -> Self {
+        Self::new_in(file, range.start, range.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.to_range()
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self::from_range_in(FileId::default(), range)
     }
 }
 
+/// `None` for empty collections.
+pub trait Spanned {
+    fn span(&self) -> Option<Span>;
+}
+
+pub fn span_of<T: Spanned + ?Sized>(t: &T) -> Option<Span> {
+    t.span()
+}
+
+fn merge_opt(a: Option<Span>, b: Option<Span>) -> Option<Span> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Spanned for Span {
+    fn span(&self) -> Option<Span> {
+        Some(*self)
+    }
+}
+
+impl<T> Spanned for S<T> {
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl<T: Spanned + ?Sized> Spanned for &T {
+    fn span(&self) -> Option<Span> {
+        (**self).span()
+    }
+}
+
+impl<T: Spanned + ?Sized> Spanned for Box<T> {
+    fn span(&self) -> Option<Span> {
+        (**self).span()
+    }
+}
+
+impl<T: Spanned> Spanned for Option<T> {
+    fn span(&self) -> Option<Span> {
+        self.as_ref().and_then(|t| t.span())
+    }
+}
+
+impl<T: Spanned> Spanned for [T] {
+    fn span(&self) -> Option<Span> {
+        self.iter().fold(None, |acc, t| merge_opt(acc, t.span()))
+    }
+}
+
+impl<T: Spanned> Spanned for Vec<T> {
+    fn span(&self) -> Option<Span> {
+        self.as_slice().span()
+    }
+}
+
+macro_rules! impl_spanned_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: Spanned),+> Spanned for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn span(&self) -> Option<Span> {
+                let ($($t,)+) = self;
+                let ret = None;
+                $(let ret = merge_opt(ret, $t.span());)+
+                ret
+            }
+        }
+    };
+}
+
+impl_spanned_tuple!(A);
+impl_spanned_tuple!(A, B);
+impl_spanned_tuple!(A, B, C);
+impl_spanned_tuple!(A, B, C, D);
+impl_spanned_tuple!(A, B, C, D, E);
+impl_spanned_tuple!(A, B, C, D, E, F);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -51,9 +268,21 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn test_new_panic2() {
-        Span::new(1, 1);
+    fn test_point() {
+        let a = Span::new(1, 1);
+        assert!(a.is_empty());
+        assert_eq!(a, Span::point(1));
+        assert_eq!(Span::point_in(FileId(1), 2).file(), FileId(1));
+        assert!(!Span::new(1, 2).is_empty());
+    }
+
+    #[test]
+    fn test_file() {
+        let a = Span::new(0, 1);
+        assert_eq!(a.file(), FileId::default());
+        let b = Span::new_in(FileId(1), 0, 1);
+        assert_eq!(b.file(), FileId(1));
+        assert_ne!(a, b);
     }
 
     #[test]
@@ -67,6 +296,7 @@ mod tests {
     fn test_len() {
         let a = Span::new(1, 2);
         assert_eq!(a.len(), 1);
+        assert_eq!(Span::point(1).len(), 0);
     }
 
     #[test]
@@ -94,5 +324,115 @@ mod tests {
         let b = Span::new(3, 4);
         let res = a.merge(b);
         assert_eq!(res, Span::new(1, 4));
+
+        let b = Span::point(5);
+        let res = a.merge(b);
+        assert_eq!(res, Span::new(1, 5));
+    }
+
+    #[test]
+    fn test_contains() {
+        let a = Span::new(1, 4);
+        assert!(a.contains(a));
+        assert!(a.contains(Span::new(2, 3)));
+        assert!(a.contains(Span::point(4)));
+        assert!(!a.contains(Span::new(0, 2)));
+        assert!(!a.contains(Span::new_in(FileId(1), 2, 3)));
+        assert!(a.contains_pos(1));
+        assert!(!a.contains_pos(4));
+    }
+
+    #[test]
+    fn test_intersect() {
+        let a = Span::new(1, 4);
+        assert_eq!(a.intersect(Span::new(2, 6)), Some(Span::new(2, 4)));
+        assert_eq!(a.intersect(Span::new(0, 2)), Some(Span::new(1, 2)));
+        assert_eq!(a.intersect(Span::new(4, 6)), Some(Span::point(4)));
+        assert_eq!(a.intersect(Span::new(5, 6)), None);
+        assert_eq!(a.intersect(Span::new_in(FileId(1), 1, 4)), None);
+    }
+
+    #[test]
+    fn test_between() {
+        let a = Span::new(1, 2);
+        let b = Span::new(4, 6);
+        assert_eq!(a.between(b), Span::new(2, 4));
+        assert_eq!(b.between(a), Span::new(2, 4));
+        assert_eq!(a.between(Span::new(2, 3)), Span::point(2));
+        assert_eq!(Span::new(0, 5).between(b), Span::point(4));
+    }
+
+    #[test]
+    fn test_shrink_shift() {
+        let a = Span::new(2, 6);
+        assert_eq!(a.shrink(1, 2), Span::new(3, 4));
+        assert_eq!(a.shrink(2, 2), Span::point(4));
+        assert_eq!(a.shift(3), Span::new(5, 9));
+        assert_eq!(a.shift(-2), Span::new(0, 4));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_shift_panic() {
+        Span::new(2, 6).shift(-3);
+    }
+
+    #[test]
+    fn test_relative() {
+        let base = Span::new(10, 20);
+        let a = Span::new(12, 15);
+        let rel = a.relative_to(base).unwrap();
+        assert_eq!(rel, Span::new(2, 5));
+        assert_eq!(base.subspan(rel.start(), rel.end()), Some(a));
+        assert_eq!(Span::new(5, 12).relative_to(base), None);
+        assert_eq!(base.subspan(5, 11), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let a = Span::new(1, 3);
+        assert_eq!(a.to_range(), 1..3);
+        let r: Range<usize> = a.into();
+        assert_eq!(r, 1..3);
+        assert_eq!(Span::from(1..3), a);
+        assert_eq!(Span::from_range_in(FileId(1), 1..3).file(), FileId(1));
+    }
+
+    #[test]
+    fn test_spanned() {
+        let a = S {
+            span: Span::new(1, 2),
+            tok: (),
+        };
+        let b = Span::new(4, 5);
+        let c = S {
+            span: Span::new(7, 9),
+            tok: (),
+        };
+        assert_eq!(span_of(&a), Some(Span::new(1, 2)));
+        assert_eq!(span_of(&(a, b, c)), Some(Span::new(1, 9)));
+        assert_eq!(span_of(&(None::<Span>, b)), Some(b));
+        assert_eq!(span_of(&vec![c, a]), Some(Span::new(1, 9)));
+        assert_eq!(span_of(&Vec::<Span>::new()), None);
+        assert_eq!(span_of(&[Some(b), None][..]), Some(b));
+        assert_eq!(span_of(&Box::new(b)), Some(b));
+    }
+
+    #[test]
+    fn test_expn() {
+        let a = Span::new(1, 4).with_expn(ExpnId(2));
+        assert_eq!(a.expn(), ExpnId(2));
+        assert_ne!(a, Span::new(1, 4));
+        assert_eq!(a.merge(Span::new(5, 6)).expn(), ExpnId(2));
+        assert_eq!(a.shrink(1, 1).expn(), ExpnId(2));
+        assert_eq!(a.subspan(0, 1).unwrap().expn(), ExpnId(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_merge_panic() {
+        let a = Span::new_in(FileId(0), 0, 1);
+        let b = Span::new_in(FileId(1), 0, 1);
+        a.merge(b);
     }
 }
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Cursors are token indices.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    Enter {
+        rule: &'static str,
+        cursor: usize,
+    },
+    /// `cursor` is where the rule ended, or where it started on a failure.
+    Exit {
+        rule: &'static str,
+        cursor: usize,
+        outcome: Outcome,
+    },
+    RollBack {
+        from: usize,
+        to: usize,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    // events with the number of rules open when they happened
+    events: Vec<(usize, Event)>,
+    rules: Vec<&'static str>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter().map(|(_, e)| e)
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub(crate) fn enter(&mut self, rule: &'static str, cursor: usize) {
+        self.events
+            .push((self.rules.len(), Event::Enter { rule, cursor }));
+        self.rules.push(rule);
+    }
+
+    pub(crate) fn exit(&mut self, cursor: usize, outcome: Outcome) {
+        let rule = self.rules.pop().expect("exit without enter");
+        let exit = Event::Exit {
+            rule,
+            cursor,
+            outcome,
+        };
+        self.events.push((self.rules.len(), exit));
+    }
+
+    pub(crate) fn roll_back(&mut self, from: usize, to: usize) {
+        if from != to {
+            let depth = self.rules.len();
+            self.events.push((depth, Event::RollBack { from, to }));
+        }
+    }
+
+    /// Rules indented by nesting, exits under their children.
+    pub fn render_tree(&self) -> String {
+        let mut ret = String::new();
+        for (depth, event) in &self.events {
+            let (indent, line) = match event {
+                Event::Enter { rule, cursor } => (*depth, format!("{} @{}", rule, cursor)),
+                Event::Exit {
+                    cursor, outcome, ..
+                } => (
+                    depth + 1,
+                    format!("=> {} @{}", outcome_str(*outcome), cursor),
+                ),
+                Event::RollBack { from, to } => (*depth, format!("<- @{} to @{}", from, to)),
+            };
+            writeln!(ret, "{:indent$}{}", "", line, indent = 2 * indent).unwrap();
+        }
+        ret
+    }
+
+    pub fn render_flat(&self) -> String {
+        let mut ret = String::new();
+        for event in self.events() {
+            match event {
+                Event::Enter { rule, cursor } => writeln!(ret, "enter {} @{}", rule, cursor),
+                Event::Exit {
+                    rule,
+                    cursor,
+                    outcome,
+                } => writeln!(ret, "{} {} @{}", outcome_str(*outcome), rule, cursor),
+                Event::RollBack { from, to } => writeln!(ret, "roll back @{} to @{}", from, to),
+            }
+            .unwrap();
+        }
+        ret
+    }
+}
+
+fn outcome_str(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Success => "ok",
+        Outcome::Failure => "fail",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace() -> Trace {
+        let mut t = Trace::new();
+        t.enter("expr", 0);
+        t.enter("term", 0);
+        t.exit(1, Outcome::Success);
+        t.roll_back(2, 2);
+        t.roll_back(2, 0);
+        t.exit(0, Outcome::Failure);
+        t
+    }
+
+    #[test]
+    fn test_events() {
+        let t = trace();
+        assert_eq!(t.events().count(), 5);
+        assert_eq!(t.events().nth(3), Some(&Event::RollBack { from: 2, to: 0 }));
+        assert_eq!(
+            t.events().last(),
+            Some(&Event::Exit {
+                rule: "expr",
+                cursor: 0,
+                outcome: Outcome::Failure
+            })
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let t = trace();
+        assert_eq!(
+            t.render_tree(),
+            "expr @0\n  term @0\n    => ok @1\n  <- @2 to @0\n  => fail @0\n"
+        );
+        assert_eq!(
+            t.render_flat(),
+            "enter expr @0\nenter term @0\nok term @1\nroll back @2 to @0\nfail expr @0\n"
+        );
+    }
+}
@@ -0,0 +1,5 @@
+reacto::error_codes! {
+    pub static REGISTRY;
+    E0001: "An unexpected token was found.\n\n```\na + + b\n```\n",
+    E0002: "The input ended in the middle of an expression.\n\n```\na +\n```\n",
+}
@@ -1,10 +1,7 @@
-use reacto::lex::{Lex, LexCtx};
-use reacto::span::Span;
+use std::fmt;
 
-#[derive(Debug)]
-pub struct LexError {
-    span: Span,
-}
+use reacto::lex::{Lex, LexCtx};
+use reacto::source_map::SourceMap;
 
 #[derive(Clone, Debug)]
 pub struct Lexer {
@@ -14,9 +11,37 @@ pub struct Lexer {
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
 pub enum Token {
     Plus,
+    Minus,
+    Star,
+    Caret,
+    Bang,
+    LParen,
+    RParen,
+    Semi,
     Whitespace,
     Ident,
     LitString,
+    Unknown,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Token::Plus => "`+`",
+            Token::Minus => "`-`",
+            Token::Star => "`*`",
+            Token::Caret => "`^`",
+            Token::Bang => "`!`",
+            Token::LParen => "`(`",
+            Token::RParen => "`)`",
+            Token::Semi => "`;`",
+            Token::Whitespace => "whitespace",
+            Token::Ident => "identifier",
+            Token::LitString => "string literal",
+            Token::Unknown => "unknown token",
+        };
+        f.write_str(s)
+    }
 }
 
 impl Lex for Lexer {
@@ -39,6 +64,13 @@ impl Lex for Lexer {
 
         let ty = match c {
             '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '^' => Token::Caret,
+            '!' => Token::Bang,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ';' => Token::Semi,
             ' ' => Token::Whitespace,
             '"' => {
                 self.advance_while(|c| c != '"');
@@ -62,6 +94,11 @@ pub fn new_lexer(s: &str) -> Lexer {
     Lexer { ctx }
 }
 
+pub fn new_lexer_in(source_map: &mut SourceMap, name: &str, s: &str) -> Lexer {
+    let ctx = LexCtx::new_in(source_map, name, s);
+    Lexer { ctx }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // helper functions
 pub fn is_digit(c: char) -> bool {
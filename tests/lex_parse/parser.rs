@@ -1,3 +1,4 @@
+use reacto::chars::Chars;
 use reacto::lex::Lex;
 use reacto::parse::{Parse, ParseCtx};
 use reacto::span::S;
@@ -14,11 +15,7 @@ pub fn new_parser_wo_sp(s: &str) -> Parser {
     Parser::new_wo_sp(lexer)
 }
 
-#[derive(Clone, Debug)]
-pub enum ParseError {
-    Expect(Token, Option<S<Token>>),
-    ExpectMulti(Vec<Token>, Option<S<Token>>),
-}
+pub type ParseError = reacto::parse::ParseError<Token>;
 
 pub struct Parser {
     ctx: ParseCtx<Token>,
@@ -28,7 +25,8 @@ impl Parser {
     pub fn new(mut lexer: Lexer) -> Self {
         let tokens = lexer.tokens().unwrap();
         let chars = lexer.chars();
-        let ctx = ParseCtx::new(chars.clone(), tokens);
+        let sink = lexer.ctx().sink().clone();
+        let ctx = ParseCtx::new(chars.clone(), tokens).with_sink(sink);
         Parser { ctx }
     }
 
@@ -40,7 +38,29 @@ impl Parser {
             .filter(|tok| tok.tok != Token::Whitespace)
             .collect();
         let chars = lexer.chars();
-        let ctx = ParseCtx::new(chars.clone(), tokens);
+        let sink = lexer.ctx().sink().clone();
+        let ctx = ParseCtx::new(chars.clone(), tokens).with_sink(sink);
+        Parser { ctx }
+    }
+
+    pub fn from_tokens(chars: Chars, tokens: Vec<S<Token>>) -> Self {
+        let ctx = ParseCtx::new(chars, tokens);
+        Parser { ctx }
+    }
+
+    pub fn memoize(self) -> Self {
+        let ctx = self.ctx.memoize(true);
+        Parser { ctx }
+    }
+
+    pub fn map_ctx(self, f: impl FnOnce(ParseCtx<Token>) -> ParseCtx<Token>) -> Self {
+        let ctx = f(self.ctx);
+        Parser { ctx }
+    }
+
+    #[cfg(feature = "trace")]
+    pub fn trace(self) -> Self {
+        let ctx = self.ctx.trace(true);
         Parser { ctx }
     }
 
@@ -62,16 +82,5 @@ impl Parse for Parser {
         &mut self.ctx
     }
 
-    fn expect_err(&self, expected: Self::Token, found: Option<S<Self::Token>>) -> Self::Error {
-        ParseError::Expect(expected, found)
-    }
-
-    fn expect_one_of_err(
-        &self,
-        expected: &[Self::Token],
-        found: Option<S<Self::Token>>,
-    ) -> Self::Error {
-        let expected = expected.iter().map(|t| t.clone()).collect();
-        ParseError::ExpectMulti(expected, found)
-    }
+    reacto::parse_errors!();
 }
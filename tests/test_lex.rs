@@ -1,7 +1,10 @@
+#[allow(dead_code)] // each test crate uses part of the harness
 mod lex_parse;
 
 use lex_parse::lexer::*;
-use reacto::lex::Lex;
+use reacto::diagnostic::Diagnostic;
+use reacto::lex::{Lex, LexCtx};
+use reacto::source_map::SourceMap;
 use reacto::span::Span;
 
 #[test]
@@ -138,8 +141,158 @@ fn test_span() {
     assert!(res.is_none());
 }
 
+#[test]
+fn test_point() {
+    let mut a = new_lexer("+ab");
+    assert_eq!(a.point(), Span::point(0));
+    a.next_s().unwrap();
+    assert_eq!(a.point(), Span::point(1));
+    a.advance();
+    assert_eq!(a.point(), Span::point(2));
+    assert_eq!(a.span(), Some(Span::new(1, 2)));
+}
+
+#[test]
+fn test_get_str() {
+    let mut a = new_lexer("\"é😀\"+ab");
+    assert_eq!(a.get_str(), None);
+    let res = a.next_s().unwrap().unwrap();
+    assert_eq!(res.span, Span::new(0, 4));
+    assert_eq!(res.tok, Token::LitString);
+    assert_eq!(a.chars().get_str(res.span).unwrap(), "\"é😀\"");
+
+    let res = a.next_s().unwrap().unwrap();
+    assert_eq!(a.chars().get_str(res.span).unwrap(), "+");
+    a.advance_while(is_letter);
+    assert_eq!(a.get_str().unwrap(), "ab");
+    assert_eq!(a.get_string().unwrap(), "ab");
+    assert_eq!(a.span(), Some(Span::new(5, 7)));
+}
+
 #[test]
 fn test_chars() {
     let a = new_lexer("+ ab");
     assert_eq!(a.chars(), "+ ab")
 }
+
+#[test]
+fn test_source_map() {
+    let mut sm = SourceMap::new();
+    let mut a = new_lexer_in(&mut sm, "a.txt", "+ab");
+    let mut b = new_lexer_in(&mut sm, "b.txt", "cd+");
+    let a_file = sm.find("a.txt").unwrap().id();
+    let b_file = sm.find("b.txt").unwrap().id();
+
+    let a_tokens = a.tokens().unwrap();
+    let b_tokens = b.tokens().unwrap();
+    assert_eq!(a_tokens[1].span, Span::new_in(a_file, 1, 3));
+    assert_eq!(b_tokens[0].span, Span::new_in(b_file, 0, 2));
+    assert_eq!(sm.get_string(a_tokens[1].span).unwrap(), "ab");
+    assert_eq!(sm.get_string(b_tokens[0].span).unwrap(), "cd");
+    assert_eq!(
+        sm.lookup(b_tokens[1].span).unwrap().to_string(),
+        "b.txt:1:3"
+    );
+}
+
+#[test]
+fn test_tokens_recover() {
+    let mut a = new_lexer("a $$ b+\"c");
+    let (tokens, errors) = a.tokens_recover(Token::Unknown);
+    let kinds: Vec<_> = tokens.iter().map(|t| t.tok).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            Token::Ident,
+            Token::Whitespace,
+            Token::Unknown,
+            Token::Whitespace,
+            Token::Ident,
+            Token::Plus,
+            Token::Unknown,
+        ]
+    );
+    assert_eq!(tokens[2].span, Span::new(2, 4));
+    assert_eq!(tokens[6].span, Span::new(7, 9));
+    let messages: Vec<_> = errors.iter().map(|e| e.error.as_str()).collect();
+    assert_eq!(messages, vec!["unknown char", "sting not closed"]);
+    assert_eq!(errors[0].span, Span::new(2, 3));
+    assert_eq!(errors[1].span, Span::new(7, 9));
+    assert!(a.eof());
+
+    let mut a = new_lexer("a+b");
+    let (tokens, errors) = a.tokens_recover(Token::Unknown);
+    assert_eq!(tokens.len(), 3);
+    assert!(errors.is_empty());
+}
+
+// lexes `a` and ` `, and warns about `b` before failing on it
+struct WarnLexer {
+    ctx: LexCtx,
+}
+
+impl Lex for WarnLexer {
+    type Token = char;
+    type Error = ();
+
+    fn ctx(&self) -> &LexCtx {
+        &self.ctx
+    }
+
+    fn ctx_mut(&mut self) -> &mut LexCtx {
+        &mut self.ctx
+    }
+
+    fn next(&mut self) -> Result<Option<char>, ()> {
+        match self.advance() {
+            Some(c @ ('a' | ' ')) => Ok(Some(c)),
+            Some('b') => {
+                let span = self.span().unwrap();
+                self.emit(Diagnostic::warning("b").with_label(span, "here"))
+                    .unwrap();
+                Err(())
+            }
+            Some(_) => Err(()),
+            None => Ok(None),
+        }
+    }
+}
+
+#[test]
+fn test_tokens_recover_probe() {
+    // recovering from `$` tries `next` at `b`, which must not emit
+    let mut a = WarnLexer {
+        ctx: LexCtx::new("$b a"),
+    };
+    let (tokens, errors) = a.tokens_recover('?');
+    assert_eq!(tokens[0].span, Span::new(0, 2));
+    assert_eq!(errors.len(), 1);
+    assert!(a.ctx().sink().diagnostics().is_empty());
+
+    let mut a = WarnLexer {
+        ctx: LexCtx::new("a b"),
+    };
+    let (_, errors) = a.tokens_recover('?');
+    assert_eq!(errors.len(), 1);
+    assert_eq!(a.ctx().sink().warning_count(), 1);
+}
+
+#[test]
+fn test_next_s_error() {
+    let mut a = new_lexer("a$+\"b");
+    assert_eq!(a.next_s().unwrap().unwrap().tok, Token::Ident);
+    let e = a.next_s().unwrap_err();
+    assert_eq!(e.span, Span::new(1, 2));
+    assert_eq!(e.error, "unknown char");
+    assert_eq!(e.to_string(), "unknown char");
+    // resumes right after the bad lexeme
+    let res = a.next_s().unwrap().unwrap();
+    assert_eq!(res.tok, Token::Plus);
+    assert_eq!(res.span, Span::new(2, 3));
+    let e = a.next_s().unwrap_err();
+    assert_eq!(e.span, Span::new(3, 5));
+    assert_eq!(a.next_s().unwrap(), None);
+
+    let e = new_lexer("ab $").tokens().unwrap_err();
+    assert_eq!(e.span, Span::new(3, 4));
+}
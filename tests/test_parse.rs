@@ -1,9 +1,24 @@
+#[allow(dead_code)] // each test crate uses part of the harness
 mod lex_parse;
 
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use lex_parse::codes;
 use lex_parse::lexer::*;
 use lex_parse::parser::*;
-use reacto::parse::Parse;
-use reacto::span::{Span, S};
+use reacto::ast::N;
+use reacto::chars::Chars;
+use reacto::diagnostic::{Applicability, Diagnostic};
+use reacto::expn::{ExpnData, ExpnKind};
+use reacto::fix;
+use reacto::lex::Lex;
+use reacto::memo::MemoStats;
+use reacto::parse::{Parse, ParseCtx, Recovered};
+use reacto::pratt::{Assoc, Pratt};
+use reacto::source_map::SourceMap;
+use reacto::span::{span_of, Span, S};
 use reacto::*;
 
 #[test]
@@ -19,6 +34,163 @@ fn test_move() {
     assert!(a.advance().is_none());
 }
 
+#[test]
+fn test_lookahead() {
+    let mut a = new_parser_wo_sp("a + b ;");
+    assert!(a.prev().is_none());
+    assert_eq!(a.peek_nth(0), a.peek());
+    assert_eq!(a.peek_nth(2).unwrap().tok, Token::Ident);
+    assert_eq!(a.peek_nth(2).unwrap().span, Span::new(4, 5));
+    assert!(a.peek_nth(4).is_none());
+    assert!(a.peek_kinds(&[Token::Ident, Token::Plus, Token::Ident]));
+    assert!(a.peek_kinds(&[]));
+    assert!(!a.peek_kinds(&[Token::Ident, Token::Minus]));
+    assert!(!a.peek_kinds(&[
+        Token::Ident,
+        Token::Plus,
+        Token::Ident,
+        Token::Semi,
+        Token::Semi
+    ]));
+
+    a.advance();
+    a.advance();
+    assert_eq!(a.prev().unwrap().tok, Token::Plus);
+    assert_eq!(a.prev().unwrap().span, Span::new(2, 3));
+    assert!(a.peek_kinds(&[Token::Ident, Token::Semi]));
+}
+
+thread_local! {
+    static CLONES: Cell<usize> = const { Cell::new(0) };
+}
+
+// a token with a payload which counts its clones
+#[derive(Debug, PartialEq, Eq)]
+enum Word {
+    Word(String),
+    Dot,
+}
+
+impl Clone for Word {
+    fn clone(&self) -> Self {
+        CLONES.with(|c| c.set(c.get() + 1));
+        match self {
+            Word::Word(w) => Word::Word(w.clone()),
+            Word::Dot => Word::Dot,
+        }
+    }
+}
+
+struct WordParser {
+    ctx: ParseCtx<Word>,
+}
+
+impl Parse for WordParser {
+    type Error = reacto::parse::ParseError<Word>;
+    type Token = Word;
+
+    fn ctx(&self) -> &ParseCtx<Self::Token> {
+        &self.ctx
+    }
+
+    fn ctx_mut(&mut self) -> &mut ParseCtx<Self::Token> {
+        &mut self.ctx
+    }
+
+    reacto::parse_errors!();
+}
+
+#[test]
+fn test_borrowed_tokens() {
+    let word = |w: &str, start| S {
+        span: Span::new(start, start + w.len()),
+        tok: Word::Word(w.to_string()),
+    };
+    let tokens = vec![
+        word("ab", 0),
+        S {
+            span: Span::new(2, 3),
+            tok: Word::Dot,
+        },
+    ];
+    let ctx = ParseCtx::new(Chars::new("ab."), tokens);
+    let mut a = WordParser { ctx };
+
+    let clones = || CLONES.with(|c| c.get());
+    CLONES.with(|c| c.set(0));
+    assert!(a.peek_kinds(&[Word::Word("ab".to_string()), Word::Dot]));
+    assert!(a.peek().is_some());
+    assert_eq!(a.peek_nth(1).unwrap().tok, Word::Dot);
+    assert_eq!(clones(), 0);
+
+    // a match clones the token once
+    assert!(a.sat(Word::Word("ab".to_string())).is_ok());
+    assert_eq!(clones(), 1);
+    let expected = [Word::Dot, Word::Word("x".to_string())];
+    a.advance_cmp(Word::Word("ab".to_string()));
+    assert!(a.sat_one_of(&expected).is_ok());
+    assert_eq!(clones(), 2);
+    assert_eq!(a.prev().unwrap().tok, Word::Word("ab".to_string()));
+}
+
+// a parser with its own error type, which does not convert from `ParseError`
+struct OwnErrorParser {
+    ctx: ParseCtx<Word>,
+}
+
+#[derive(Debug, PartialEq)]
+enum OwnError {
+    Expect(Vec<Word>, Option<Span>),
+}
+
+impl Parse for OwnErrorParser {
+    type Error = OwnError;
+    type Token = Word;
+
+    fn ctx(&self) -> &ParseCtx<Self::Token> {
+        &self.ctx
+    }
+
+    fn ctx_mut(&mut self) -> &mut ParseCtx<Self::Token> {
+        &mut self.ctx
+    }
+
+    fn expect_err(&self, expected: Self::Token, found: Option<S<Self::Token>>) -> Self::Error {
+        OwnError::Expect(vec![expected], found.map(|d| d.span))
+    }
+
+    fn expect_one_of_err(
+        &self,
+        expected: &[Self::Token],
+        found: Option<S<Self::Token>>,
+    ) -> Self::Error {
+        OwnError::Expect(expected.to_vec(), found.map(|d| d.span))
+    }
+}
+
+#[test]
+fn test_own_error() {
+    let tokens = vec![S {
+        span: Span::new(0, 1),
+        tok: Word::Dot,
+    }];
+    let ctx = ParseCtx::new(Chars::new("."), tokens.clone());
+    let a = OwnErrorParser { ctx };
+    assert_eq!(
+        a.sat(Word::Word("a".to_string())),
+        Err(OwnError::Expect(
+            vec![Word::Word("a".to_string())],
+            Some(Span::new(0, 1))
+        ))
+    );
+
+    // limits are reported through `parse_err`, at the token where parsing stopped
+    let ctx = ParseCtx::new(Chars::new("."), tokens).recursion_limit(0);
+    let mut a = OwnErrorParser { ctx };
+    let e = a.parse(|p| p.expect(Word::Dot)).unwrap_err();
+    assert_eq!(e, OwnError::Expect(vec![], Some(Span::new(0, 1))));
+}
+
 #[test]
 fn test_advance_cmp() {
     let mut a = new_parser("a+");
@@ -49,10 +221,10 @@ fn test_expect() {
 
     // failed
     match a.expect(Token::Plus).unwrap_err() {
-        ParseError::Expect(expected, found) => {
-            assert_eq!(expected, Token::Plus);
-            assert_eq!(found.unwrap().span, Span::new(2, 3));
-            assert_eq!(found.unwrap().tok, Token::Ident)
+        ParseError::Expect { expected, found } => {
+            assert_eq!(expected, vec![Token::Plus]);
+            assert_eq!(found.span, Span::new(2, 3));
+            assert_eq!(found.tok, Token::Ident)
         }
         _ => panic!("invalid error"),
     }
@@ -63,9 +235,9 @@ fn test_expect() {
 
     // eof
     match a.expect(Token::Plus).unwrap_err() {
-        ParseError::Expect(expected, found) => {
-            assert_eq!(expected, Token::Plus);
-            assert!(found.is_none());
+        ParseError::Eof { expected, span } => {
+            assert_eq!(expected, vec![Token::Plus]);
+            assert_eq!(span, Span::point(3));
         }
         _ => panic!("invalid error"),
     }
@@ -85,10 +257,10 @@ fn test_sat() {
 
     // failed
     match a.sat(Token::Plus).unwrap_err() {
-        ParseError::Expect(expected, found) => {
-            assert_eq!(expected, Token::Plus);
-            assert_eq!(found.unwrap().span, Span::new(0, 1));
-            assert_eq!(found.unwrap().tok, Token::Ident)
+        ParseError::Expect { expected, found } => {
+            assert_eq!(expected, vec![Token::Plus]);
+            assert_eq!(found.span, Span::new(0, 1));
+            assert_eq!(found.tok, Token::Ident)
         }
         _ => panic!("invalid error"),
     }
@@ -100,9 +272,9 @@ fn test_sat() {
     // eof
     let a = new_parser("");
     match a.sat(Token::Plus).unwrap_err() {
-        ParseError::Expect(expected, found) => {
-            assert_eq!(expected, Token::Plus);
-            assert!(found.is_none());
+        ParseError::Eof { expected, span } => {
+            assert_eq!(expected, vec![Token::Plus]);
+            assert_eq!(span, Span::point(0));
         }
         _ => panic!("invalid error"),
     }
@@ -125,10 +297,10 @@ fn test_expect_one_of() {
         .expect_one_of(&[Token::Plus, Token::Whitespace])
         .unwrap_err()
     {
-        ParseError::ExpectMulti(expected, found) => {
+        ParseError::Expect { expected, found } => {
             assert_eq!(expected, vec![Token::Plus, Token::Whitespace]);
-            assert_eq!(found.unwrap().span, Span::new(2, 3));
-            assert_eq!(found.unwrap().tok, Token::Ident)
+            assert_eq!(found.span, Span::new(2, 3));
+            assert_eq!(found.tok, Token::Ident)
         }
         _ => panic!("invalid error"),
     }
@@ -142,9 +314,9 @@ fn test_expect_one_of() {
         .expect_one_of(&[Token::Plus, Token::Whitespace])
         .unwrap_err()
     {
-        ParseError::ExpectMulti(expected, found) => {
+        ParseError::Eof { expected, span } => {
             assert_eq!(expected, vec![Token::Plus, Token::Whitespace]);
-            assert!(found.is_none());
+            assert_eq!(span, Span::point(3));
         }
         _ => panic!("invalid error"),
     }
@@ -164,10 +336,10 @@ fn test_sat_one_of() {
 
     // failed
     match a.sat_one_of(&[Token::Plus, Token::Whitespace]).unwrap_err() {
-        ParseError::ExpectMulti(expected, found) => {
+        ParseError::Expect { expected, found } => {
             assert_eq!(expected, vec![Token::Plus, Token::Whitespace]);
-            assert_eq!(found.unwrap().span, Span::new(0, 1));
-            assert_eq!(found.unwrap().tok, Token::Ident)
+            assert_eq!(found.span, Span::new(0, 1));
+            assert_eq!(found.tok, Token::Ident)
         }
         _ => panic!("invalid error"),
     }
@@ -179,20 +351,840 @@ fn test_sat_one_of() {
     // eof
     let a = new_parser("");
     match a.sat_one_of(&[Token::Plus, Token::Whitespace]).unwrap_err() {
-        ParseError::ExpectMulti(expected, found) => {
+        ParseError::Eof { expected, span } => {
             assert_eq!(expected, vec![Token::Plus, Token::Whitespace]);
-            assert!(found.is_none());
+            assert_eq!(span, Span::point(0));
         }
         _ => panic!("invalid error"),
     }
 }
 
+#[test]
+fn test_expect_diagnostic() {
+    let mut a = new_parser("a+");
+    let e = a.sat(Token::Plus).unwrap_err();
+    let d = a.expect_diagnostic(e.expected(), e.found());
+    assert_eq!(d.to_string(), "error: expected `+`, found identifier");
+    assert_eq!(d.span(), Some(Span::new(0, 1)));
+
+    a.advance();
+    a.advance();
+    let e = a.sat_one_of(&[Token::Plus, Token::Ident]).unwrap_err();
+    let d = a.expect_diagnostic(e.expected(), e.found());
+    assert_eq!(d.message, "expected `+` or identifier, found end of file");
+    assert_eq!(d.span(), Some(Span::point(2)));
+}
+
+#[test]
+fn test_parse_error() {
+    let mut a = new_parser("a+");
+    let e = a.sat(Token::Plus).unwrap_err();
+    assert_eq!(e.to_string(), "expected `+`, found identifier");
+    assert_eq!(e.span(), Span::new(0, 1));
+    let d = Diagnostic::from(e);
+    assert_eq!(d.to_string(), "error: expected `+`, found identifier");
+    assert_eq!(d.span(), Some(Span::new(0, 1)));
+
+    a.advance();
+    a.advance();
+    let e = a.sat_one_of(&[Token::Plus, Token::Ident]).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "expected `+` or identifier, found end of file"
+    );
+    assert_eq!(e.found(), None);
+    assert_eq!(Diagnostic::from(e).span(), Some(Span::point(2)));
+}
+
+#[test]
+fn test_farthest_error() {
+    let alternatives = |p: &mut Parser| -> Result<(), ParseError> {
+        let two = |second| {
+            move |p: &mut Parser| -> Result<(), ParseError> {
+                p.expect(Token::Ident)?;
+                p.expect(second)?;
+                Ok(())
+            }
+        };
+        if p.parse_roll_back(two(Token::Ident)).is_ok() {
+            return Ok(());
+        }
+        if p.parse_roll_back(two(Token::LitString)).is_ok() {
+            return Ok(());
+        }
+        p.expect(Token::Plus)?;
+        Ok(())
+    };
+
+    let mut a = new_parser_wo_sp("a + b");
+    let e = alternatives(&mut a).unwrap_err();
+    assert_eq!(e.to_string(), "expected `+`, found identifier");
+    let (cursor, expected) = a.ctx().farthest_failure().unwrap();
+    assert_eq!(cursor, 1);
+    assert_eq!(expected, vec![Token::Ident, Token::LitString]);
+
+    let e = a.parse_farthest(alternatives).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "expected identifier or string literal, found `+`"
+    );
+    assert_eq!(e.span(), Span::new(2, 3));
+    assert_eq!(a.cursor(), 0);
+
+    let mut a = new_parser_wo_sp("a");
+    let e = a.parse_farthest(alternatives).unwrap_err();
+    assert_eq!(
+        e,
+        ParseError::Eof {
+            expected: vec![Token::Ident, Token::LitString],
+            span: Span::point(1)
+        }
+    );
+    assert!(a.parse_farthest(alternatives).is_err());
+
+    let mut a = new_parser_wo_sp("a b");
+    assert!(a.parse_farthest(alternatives).is_ok());
+
+    // a limit hit inside is kept
+    let mut a = new_parser_wo_sp("a + b").map_ctx(|c| c.fuel(2));
+    let e = a.parse_farthest(alternatives).unwrap_err();
+    assert!(matches!(e, ParseError::FuelExhausted { .. }));
+
+    // so is an error made by hand past the farthest failure
+    let custom = |p: &mut Parser| -> Result<(), ParseError> {
+        let pair = p.parse_roll_back(|p| {
+            p.expect(Token::Ident)?;
+            p.expect(Token::Ident)
+        });
+        if pair.is_ok() {
+            return Ok(());
+        }
+        p.expect(Token::Ident)?;
+        p.expect(Token::Plus)?;
+        let found = p.expect(Token::Ident)?;
+        Err(ParseError::Expect {
+            expected: vec![],
+            found,
+        })
+    };
+    let mut a = new_parser_wo_sp("a + b");
+    let e = a.parse_farthest(custom).unwrap_err();
+    assert_eq!(e.span(), Span::new(4, 5));
+    assert_eq!(a.ctx().farthest_failure().unwrap().0, 1);
+}
+
+#[test]
+fn test_expect_suggestion() {
+    let mut sm = SourceMap::new();
+    let lexer = new_lexer_in(&mut sm, "main.x", "a b");
+    let mut a = Parser::new_wo_sp(lexer);
+    a.advance();
+    let d = Diagnostic::from(a.sat(Token::Plus).unwrap_err()).with_replacement(
+        a.point(),
+        "add `+`",
+        "+",
+        Applicability::MachineApplicable,
+    );
+    let chars = sm.find("main.x").unwrap().chars();
+    let fixed = fix::apply_diagnostics(chars, vec![&d]);
+    assert_eq!(fixed.source, "a+ b");
+}
+
+#[test]
+fn test_sink() {
+    let lexer = new_lexer("a + b c");
+    lexer
+        .emit(Diagnostic::warning("w").with_primary(Span::new(6, 7), ""))
+        .unwrap();
+    let mut a = Parser::new_wo_sp(lexer);
+    let sink = a.ctx().sink().clone().error_limit(2);
+    while !a.eof() {
+        if let Err(e) = a.expect(Token::Ident) {
+            if a.emit(e.into()).is_err() {
+                break;
+            }
+            a.advance();
+        }
+    }
+    assert_eq!(sink.error_count(), 1);
+    assert_eq!(sink.warning_count(), 1);
+    let messages: Vec<_> = sink.diagnostics().into_iter().map(|d| d.message).collect();
+    assert_eq!(messages, vec!["expected identifier, found `+`", "w"]);
+}
+
+#[test]
+fn test_registered_codes() {
+    let mut a = new_parser_wo_sp("a + + b +");
+    while !a.eof() {
+        if let Err(e) = a.expect(Token::Ident) {
+            let code = match e {
+                ParseError::Eof { .. } => codes::E0002,
+                _ => codes::E0001,
+            };
+            a.emit(Diagnostic::from(e).with_code(code)).unwrap();
+            a.advance();
+        }
+        a.advance_cmp(Token::Plus);
+    }
+    let e = a.sat(Token::Ident).unwrap_err();
+    a.emit(Diagnostic::from(e).with_code(codes::E0002)).unwrap();
+
+    let diags = a.ctx().sink().diagnostics();
+    assert_eq!(diags.len(), 2);
+    assert!(codes::REGISTRY.unregistered(&diags).is_empty());
+    assert!(codes::REGISTRY.duplicates().is_empty());
+    assert!(codes::REGISTRY.explain("E0002").unwrap().contains("a +"));
+}
+
+#[test]
+fn test_parse_recovered_tokens() {
+    let mut lexer = new_lexer("a + $ + b");
+    let (tokens, errors) = lexer.tokens_recover(Token::Unknown);
+    assert_eq!(errors.len(), 1);
+    let tokens = tokens
+        .into_iter()
+        .filter(|t| t.tok != Token::Whitespace)
+        .collect();
+    let mut a = Parser::from_tokens(lexer.chars().clone(), tokens);
+    a.expect(Token::Ident).unwrap();
+    a.expect(Token::Plus).unwrap();
+    let e = a.expect(Token::Ident).unwrap_err();
+    assert_eq!(e.to_string(), "expected identifier, found unknown token");
+    assert_eq!(e.span(), Span::new(4, 5));
+    a.advance();
+    a.expect(Token::Plus).unwrap();
+    a.expect(Token::Ident).unwrap();
+    assert!(a.eof());
+}
+
+fn pratt_table() -> Pratt<Token, String> {
+    let op = |t: &S<Token>| t.tok.to_string().trim_matches('`').to_string();
+    Pratt::new()
+        .prefix(Token::Minus, 3, move |o, e| {
+            format!("({}{})", op(&o), e.data)
+        })
+        .infix(Token::Plus, 1, Assoc::Left, move |l, o, r| {
+            format!("({} {} {})", l.data, op(&o), r.data)
+        })
+        .infix(Token::Minus, 1, Assoc::Left, move |l, o, r| {
+            format!("({} {} {})", l.data, op(&o), r.data)
+        })
+        .infix(Token::Star, 2, Assoc::Left, move |l, o, r| {
+            format!("({} {} {})", l.data, op(&o), r.data)
+        })
+        .infix(Token::Caret, 4, Assoc::Right, move |l, o, r| {
+            format!("({} {} {})", l.data, op(&o), r.data)
+        })
+        .postfix(Token::Bang, 5, move |e, o| {
+            format!("({}{})", e.data, op(&o))
+        })
+}
+
+fn parse_pratt(s: &str) -> Result<N<String>, ParseError> {
+    let mut a = new_parser_wo_sp(s);
+    let atom = |p: &mut Parser| {
+        let t = p.expect(Token::Ident)?;
+        let name = p.chars().get_string(t.span).unwrap();
+        Ok(p.make_node_at(t.span, name))
+    };
+    let ret = a.parse_pratt(&pratt_table(), atom);
+    if ret.is_err() {
+        assert_eq!(a.cursor(), 0);
+    }
+    ret
+}
+
+#[test]
+fn test_pratt() {
+    let cases = [
+        ("a", "a"),
+        ("a + b * c", "(a + (b * c))"),
+        ("a - b - c", "((a - b) - c)"),
+        ("a ^ b ^ c", "(a ^ (b ^ c))"),
+        ("-a * b", "((-a) * b)"),
+        ("-a ^ b", "(-(a ^ b))"),
+        ("- - a!", "(-(-(a!)))"),
+        ("a * b! + c", "((a * (b!)) + c)"),
+        ("a ^ b!", "(a ^ (b!))"),
+    ];
+    for (input, expected) in cases {
+        let e = parse_pratt(input).unwrap();
+        assert_eq!(e.data, expected, "{}", input);
+        assert_eq!(e.span, Span::new(0, input.len()), "{}", input);
+    }
+
+    // spans cover the operator expression only
+    let mut a = new_parser_wo_sp("a b * c");
+    a.advance();
+    let atom = |p: &mut Parser| {
+        let t = p.expect(Token::Ident)?;
+        Ok(p.make_node_at(t.span, String::new()))
+    };
+    let e = a.parse_pratt(&pratt_table(), atom).unwrap();
+    assert_eq!(e.span, Span::new(2, 7));
+}
+
+#[test]
+fn test_pratt_error() {
+    assert_eq!(
+        parse_pratt("a + * b").unwrap_err().to_string(),
+        "expected identifier, found `*`"
+    );
+    assert!(matches!(
+        parse_pratt("a +").unwrap_err(),
+        ParseError::Eof { .. }
+    ));
+}
+
+// e := t '+' e | t '*' e | t
+// t := '-' e '!' | ident
+fn parse_e(p: &mut Parser, calls: &Cell<usize>) -> Result<(), ParseError> {
+    for op in [Token::Plus, Token::Star] {
+        let alt = p.parse_roll_back(|p: &mut Parser| {
+            parse_t(p, calls)?;
+            p.expect(op)?;
+            parse_e(p, calls)
+        });
+        if alt.is_ok() {
+            return Ok(());
+        }
+    }
+    parse_t(p, calls)
+}
+
+fn parse_t(p: &mut Parser, calls: &Cell<usize>) -> Result<(), ParseError> {
+    p.parse_memo("t", |p: &mut Parser| {
+        calls.set(calls.get() + 1);
+        if p.advance_cmp(Token::Minus) {
+            parse_e(p, calls)?;
+            p.expect(Token::Bang)?;
+        } else {
+            p.expect(Token::Ident)?;
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_parse_memo() {
+    let input = "- - - - - a ! ! ! ! ! * b";
+    let calls = Cell::new(0);
+    let mut a = new_parser_wo_sp(input);
+    parse_e(&mut a, &calls).unwrap();
+    assert!(a.eof());
+    let plain = calls.get();
+    assert_eq!(a.ctx().memo_stats(), MemoStats::default());
+
+    let calls = Cell::new(0);
+    let mut a = new_parser_wo_sp(input).memoize();
+    parse_e(&mut a, &calls).unwrap();
+    assert!(a.eof());
+    // once per cursor a `t` can start at
+    assert_eq!(calls.get(), 7);
+    assert!(plain > 100);
+
+    let stats = a.ctx().memo_stats();
+    assert_eq!(stats.misses, 7);
+    assert_eq!(stats.entries, 7);
+    assert!(stats.hits > stats.misses);
+    assert!(stats.hit_rate() > 0.5);
+
+    // errors are cached as well, and roll back
+    let calls = Cell::new(0);
+    let mut a = new_parser_wo_sp("- a +").memoize();
+    assert!(parse_t(&mut a, &calls).is_err());
+    let hits = a.ctx().memo_stats().hits;
+    assert!(parse_t(&mut a, &calls).is_err());
+    assert_eq!(a.cursor(), 0);
+    assert_eq!(a.ctx().memo_stats().hits, hits + 1);
+
+    // a hit replays the expectations, so the farthest error is the same as without the memo
+    let pair = |p: &mut Parser| {
+        p.parse_memo("pair", |p: &mut Parser| {
+            p.expect(Token::Ident)?;
+            p.expect(Token::LitString)
+        })
+    };
+    let alternatives = |p: &mut Parser| -> Result<(), ParseError> {
+        if p.parse_roll_back(|p| {
+            pair(p)?;
+            p.expect(Token::Plus)
+        })
+        .is_ok()
+        {
+            return Ok(());
+        }
+        pair(p)?;
+        p.expect(Token::Minus)?;
+        Ok(())
+    };
+    for input in ["a b", "a \"b\" *", "a"] {
+        let mut plain = new_parser_wo_sp(input);
+        let mut memo = new_parser_wo_sp(input).memoize();
+        for _ in 0..2 {
+            let e = plain.parse_farthest(alternatives);
+            assert_eq!(memo.parse_farthest(alternatives), e);
+            assert_eq!(
+                memo.ctx().farthest_failure(),
+                plain.ctx().farthest_failure()
+            );
+        }
+        assert!(memo.ctx().memo_stats().hits > 0);
+    }
+
+    // nothing is cached once a limit is hit
+    let mut a = new_parser_wo_sp("a \"b\"").memoize().map_ctx(|c| c.fuel(1));
+    let e = pair(&mut a).unwrap_err();
+    assert!(matches!(e, ParseError::FuelExhausted { .. }));
+    assert_eq!(a.ctx().memo_stats().entries, 0);
+    a.ctx_mut().reset_limits();
+    let mut a = a.map_ctx(|c| c.fuel(100));
+    assert_eq!(pair(&mut a).unwrap().span, Span::new(2, 5));
+}
+
+#[test]
+fn test_ctx_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ParseCtx<u8>>();
+}
+
+// expr := expr '+' term | expr '-' term | term
+// term := term '*' ident | ident
+fn parse_expr(p: &mut Parser) -> Result<N<String>, ParseError> {
+    p.parse_left_rec("expr", |p: &mut Parser| {
+        for op in [Token::Plus, Token::Minus] {
+            let alt = p.parse_n(|p: &mut Parser| {
+                let l = parse_expr(p)?;
+                let o = p.expect(op)?;
+                let r = parse_term(p)?;
+                Ok(format!("({} {} {})", l.data, op_str(&o), r.data))
+            });
+            if alt.is_ok() {
+                return alt;
+            }
+        }
+        parse_term(p)
+    })
+}
+
+fn parse_term(p: &mut Parser) -> Result<N<String>, ParseError> {
+    p.parse_left_rec("term", |p: &mut Parser| {
+        let alt = p.parse_n(|p: &mut Parser| {
+            let l = parse_term(p)?;
+            p.expect(Token::Star)?;
+            let r = parse_ident(p)?;
+            Ok(format!("({} * {})", l.data, r.data))
+        });
+        alt.or_else(|_| parse_ident(p))
+    })
+}
+
+fn parse_ident(p: &mut Parser) -> Result<N<String>, ParseError> {
+    let t = p.expect(Token::Ident)?;
+    let name = p.chars().get_string(t.span).unwrap();
+    Ok(p.make_node_at(t.span, name))
+}
+
+fn op_str(t: &S<Token>) -> String {
+    t.tok.to_string().trim_matches('`').to_string()
+}
+
+#[test]
+fn test_parse_left_rec() {
+    let cases = [
+        ("a", "a"),
+        ("a + b", "(a + b)"),
+        ("a - b + c - d", "(((a - b) + c) - d)"),
+        ("a * b * c + d * e", "(((a * b) * c) + (d * e))"),
+    ];
+    for (input, expected) in cases {
+        let mut a = new_parser_wo_sp(input);
+        let e = parse_expr(&mut a).unwrap();
+        assert_eq!(e.data, expected, "{}", input);
+        assert_eq!(e.span, Span::new(0, input.len()), "{}", input);
+        assert!(a.eof());
+    }
+
+    // spans end at the last operand
+    let mut a = new_parser_wo_sp("a + b + c");
+    let e = parse_expr(&mut a).unwrap();
+    assert_eq!(e.span, Span::new(0, 9));
+    let mut a = new_parser_wo_sp("a + b ;");
+    let e = parse_expr(&mut a).unwrap();
+    assert_eq!(e.span, Span::new(0, 5));
+    assert_eq!(a.peek().unwrap().tok, Token::Semi);
+
+    // stops before a trailing operator without an operand
+    let mut a = new_parser_wo_sp("a + b +");
+    assert_eq!(parse_expr(&mut a).unwrap().data, "(a + b)");
+    assert_eq!(a.cursor(), 3);
+
+    let mut a = new_parser_wo_sp("+ a");
+    assert_eq!(
+        parse_expr(&mut a).unwrap_err().to_string(),
+        "expected identifier, found `+`"
+    );
+    assert_eq!(a.cursor(), 0);
+}
+
+#[test]
+fn test_parse_left_rec_no_base_case() {
+    // a := a '+'
+    fn parse_a(p: &mut Parser) -> Result<N<()>, ParseError> {
+        p.parse_left_rec("a", |p: &mut Parser| {
+            p.parse_n(|p: &mut Parser| {
+                parse_a(p)?;
+                p.expect(Token::Plus)?;
+                Ok(())
+            })
+        })
+    }
+    let mut a = new_parser_wo_sp("b +");
+    let e = parse_a(&mut a).unwrap_err();
+    assert_eq!(
+        e,
+        ParseError::LeftRecursion {
+            rule: "a",
+            span: Span::new(0, 1)
+        }
+    );
+    assert_eq!(
+        e.to_string(),
+        "left recursive rule `a` has no base case here"
+    );
+    assert_eq!(a.cursor(), 0);
+}
+
+// x := y
+// y := x '+' ident | ident
+fn parse_x(p: &mut Parser) -> Result<N<()>, ParseError> {
+    p.parse_left_rec("x", parse_y)
+}
+
+fn parse_y(p: &mut Parser) -> Result<N<()>, ParseError> {
+    p.parse_memo("y", |p: &mut Parser| {
+        let alt = p.parse_n(|p: &mut Parser| {
+            parse_x(p)?;
+            p.expect(Token::Plus)?;
+            p.expect(Token::Ident)?;
+            Ok(())
+        });
+        alt.or_else(|_| p.parse_n(|p: &mut Parser| p.expect(Token::Ident).map(|_| ())))
+    })
+}
+
+#[test]
+fn test_parse_left_rec_indirect() {
+    let mut a = new_parser_wo_sp("a+a+a");
+    assert_eq!(parse_x(&mut a).unwrap().span, Span::new(0, 5));
+    assert!(a.eof());
+
+    let mut a = new_parser_wo_sp("a+a+a").memoize();
+    assert_eq!(parse_x(&mut a).unwrap().span, Span::new(0, 5));
+    assert!(a.eof());
+    // nothing is cached while `x` grows
+    assert_eq!(a.ctx().memo_stats().entries, 0);
+}
+
+// group := '(' group ')' | ident
+fn parse_group(p: &mut Parser) -> Result<(), ParseError> {
+    p.parse(|p: &mut Parser| {
+        if p.advance_cmp(Token::LParen) {
+            parse_group(p)?;
+            p.expect(Token::RParen)?;
+        } else {
+            p.expect(Token::Ident)?;
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_recursion_limit() {
+    let mut a = new_parser("((a))").map_ctx(|c| c.recursion_limit(3));
+    parse_group(&mut a).unwrap();
+
+    let mut a = new_parser("(((a)))").map_ctx(|c| c.recursion_limit(3));
+    let e = parse_group(&mut a).unwrap_err();
+    assert_eq!(
+        e,
+        ParseError::RecursionLimit {
+            limit: 3,
+            span: Span::new(3, 4)
+        }
+    );
+    assert_eq!(a.cursor(), 0);
+    let d = Diagnostic::from(e);
+    assert_eq!(d.message, "nesting exceeds the limit of 3");
+    assert_eq!(d.span(), Some(Span::new(3, 4)));
+
+    // deep enough to overflow the stack without the limit
+    let n = 100_000;
+    let input = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+    let mut a = new_parser(&input).map_ctx(|c| c.recursion_limit(200));
+    let e = parse_group(&mut a).unwrap_err();
+    assert_eq!(e.span(), Span::new(200, 201));
+
+    // `parse_recover` counts as a rule
+    let mut a = new_parser("a + a").map_ctx(|c| c.recursion_limit(0));
+    let stmt = a.parse_recover(&[], parse_stmt, |e| Stmt::Error(e.to_string()));
+    assert!(matches!(
+        stmt.errors[..],
+        [ParseError::RecursionLimit { .. }]
+    ));
+}
+
+#[test]
+fn test_recursion_limit_pratt() {
+    let atom = |p: &mut Parser| {
+        let t = p.expect(Token::Ident)?;
+        Ok(p.make_node_at(t.span, ()))
+    };
+    let pratt = Pratt::new().prefix(Token::Minus, 3, |_, _| ()).infix(
+        Token::Caret,
+        4,
+        Assoc::Right,
+        |_, _, _| (),
+    );
+
+    let input = "-".repeat(1_000_000) + "a";
+    let mut a = new_parser_wo_sp(&input).map_ctx(|c| c.recursion_limit(100));
+    let e = a.parse_pratt(&pratt, atom).unwrap_err();
+    assert!(matches!(e, ParseError::RecursionLimit { limit: 100, .. }));
+    assert_eq!(a.cursor(), 0);
+
+    let input = "a^".repeat(1_000_000) + "a";
+    let mut a = new_parser_wo_sp(&input).map_ctx(|c| c.recursion_limit(100));
+    let e = a.parse_pratt(&pratt, atom).unwrap_err();
+    assert!(matches!(e, ParseError::RecursionLimit { limit: 100, .. }));
+
+    let mut a = new_parser_wo_sp("--a^a").map_ctx(|c| c.recursion_limit(100));
+    assert!(a.parse_pratt(&pratt, atom).is_ok());
+}
+
+#[test]
+fn test_fuel() {
+    let input = "- - - - - - - - - - a ! ! ! ! ! ! ! ! ! !";
+    let calls = Cell::new(0);
+    let mut a = new_parser_wo_sp(input).map_ctx(|c| c.fuel(1000));
+    assert_eq!(a.ctx().fuel_left(), Some(1000));
+    let e = parse_e(&mut a, &calls).unwrap_err();
+    assert!(matches!(e, ParseError::FuelExhausted { .. }));
+    assert_eq!(e.to_string(), "parsing ran out of fuel");
+    assert_eq!(a.ctx().fuel_left(), Some(0));
+    assert_eq!(a.cursor(), 0);
+    // the error sticks, so every alternative fails at once
+    let calls_after = calls.get();
+    assert_eq!(parse_e(&mut a, &calls).unwrap_err(), e);
+    assert_eq!(calls.get(), calls_after);
+    a.ctx_mut().reset_limits();
+    assert_eq!(a.ctx().fuel_left(), Some(1000));
+    assert!(a.expect(Token::Minus).is_ok());
+
+    let mut a = new_parser_wo_sp("a + b").map_ctx(|c| c.fuel(1000));
+    parse_e(&mut a, &Cell::new(0)).unwrap();
+    assert!(a.ctx().fuel_left().unwrap() < 1000);
+
+    // `peek` still sees the tokens, the rule fails when it ends
+    let mut a = new_parser_wo_sp("a a a a").map_ctx(|c| c.fuel(2));
+    let ret = a.parse(|p: &mut Parser| {
+        while p.peek().is_some() {
+            p.advance();
+        }
+        Ok(())
+    });
+    assert!(matches!(ret, Err(ParseError::FuelExhausted { .. })));
+    assert_eq!(a.cursor(), 0);
+}
+
+#[test]
+fn test_cancel() {
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut a = new_parser_wo_sp("a ; a ; a").map_ctx(|c| c.cancel_on(flag.clone()));
+    let ret = a.parse(|p: &mut Parser| {
+        p.expect(Token::Ident)?;
+        flag.store(true, Ordering::Relaxed);
+        p.expect(Token::Semi)
+    });
+    assert_eq!(
+        ret.unwrap_err(),
+        ParseError::Cancelled {
+            span: Span::new(2, 3)
+        }
+    );
+    assert_eq!(a.cursor(), 0);
+    // the stop sticks until the limits are reset
+    flag.store(false, Ordering::Relaxed);
+    assert!(a.expect(Token::Ident).is_err());
+    a.ctx_mut().reset_limits();
+    assert!(a.expect(Token::Ident).is_ok());
+
+    // recovery still ends, every statement after the cancel is an error
+    flag.store(false, Ordering::Relaxed);
+    let mut a = new_parser_wo_sp("a + a ; a + a ; a + a").map_ctx(|c| c.cancel_on(flag.clone()));
+    let first = a.parse_recover(&[Token::Semi], parse_stmt, |_| Stmt::Error(String::new()));
+    assert_eq!(first.value.data, Stmt::Add);
+    assert!(first.errors.is_empty());
+    a.advance_cmp(Token::Semi);
+    flag.store(true, Ordering::Relaxed);
+    let stmts = parse_stmts(&mut a);
+    assert!(a.eof());
+    assert_eq!(stmts.errors.len(), 2);
+    assert!(stmts
+        .errors
+        .iter()
+        .all(|e| matches!(e, ParseError::Cancelled { .. })));
+
+    // a cancel which `f` ignores fails `parse_recover` and `parse_roll_back_opt` too
+    flag.store(false, Ordering::Relaxed);
+    let mut a = new_parser_wo_sp("a + a ; a").map_ctx(|c| c.cancel_on(flag.clone()));
+    let ret = a.parse_recover(
+        &[Token::Semi],
+        |p: &mut Parser| {
+            let stmt = parse_stmt(p)?;
+            flag.store(true, Ordering::Relaxed);
+            p.peek();
+            Ok(stmt)
+        },
+        |_| Stmt::Error(String::new()),
+    );
+    assert_eq!(ret.value.data, Stmt::Error(String::new()));
+    assert!(matches!(ret.errors[..], [ParseError::Cancelled { .. }]));
+
+    flag.store(false, Ordering::Relaxed);
+    let mut a = new_parser_wo_sp("a ; a").map_ctx(|c| c.cancel_on(flag.clone()));
+    let ret = a.parse_roll_back_opt(|p: &mut Parser| {
+        let d = p.expect(Token::Ident)?;
+        flag.store(true, Ordering::Relaxed);
+        p.peek();
+        Ok(Some(d))
+    });
+    assert!(matches!(ret, Err(ParseError::Cancelled { .. })));
+    assert_eq!(a.cursor(), 0);
+}
+
+#[derive(Debug, PartialEq)]
+enum Stmt {
+    Add,
+    Group(Box<N<Stmt>>),
+    Error(String),
+}
+
+// stmt := ident '+' ident | '(' stmt ')'
+fn parse_stmt(p: &mut Parser) -> Result<N<Stmt>, ParseError> {
+    p.parse_n(|p| {
+        if p.advance_cmp(Token::LParen) {
+            let inner = parse_stmt(p)?;
+            p.expect(Token::RParen)?;
+            Ok(Stmt::Group(Box::new(inner)))
+        } else {
+            p.expect(Token::Ident)?;
+            p.expect(Token::Plus)?;
+            p.expect(Token::Ident)?;
+            Ok(Stmt::Add)
+        }
+    })
+}
+
+fn parse_stmts(p: &mut Parser) -> Recovered<Vec<N<Stmt>>, ParseError> {
+    let mut errors = vec![];
+    let mut stmts = vec![];
+    while !p.eof() {
+        let stmt = p
+            .parse_recover(&[Token::Semi], parse_stmt, |e| Stmt::Error(e.to_string()))
+            .take(&mut errors);
+        stmts.push(stmt);
+        p.advance_cmp(Token::Semi);
+    }
+    Recovered {
+        value: stmts,
+        errors,
+    }
+}
+
+#[test]
+fn test_parse_recover() {
+    let mut a = new_parser_wo_sp("a + b; c + ; (d + * e); f + g");
+    let ret = parse_stmts(&mut a);
+    assert!(ret.has_errors());
+    let messages: Vec<_> = ret.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "expected identifier, found `;`",
+            "expected identifier, found `*`"
+        ]
+    );
+    let stmts = ret.value;
+    assert_eq!(stmts.len(), 4);
+    assert_eq!(stmts[0].data, Stmt::Add);
+    assert_eq!(stmts[1].data, Stmt::Error(messages[0].clone()));
+    assert_eq!(stmts[1].span, Span::new(7, 10));
+    assert_eq!(stmts[2].data, Stmt::Error(messages[1].clone()));
+    assert_eq!(stmts[2].span, Span::new(13, 22));
+    assert_eq!(stmts[3].span, Span::new(24, 29));
+
+    // an empty statement is skipped as a whole, so the loop advances
+    let mut a = new_parser_wo_sp(";(a + b)");
+    let ret = parse_stmts(&mut a);
+    assert_eq!(ret.errors.len(), 1);
+    assert_eq!(ret.value[0].span, Span::new(0, 1));
+    assert!(matches!(ret.value[1].data, Stmt::Group(_)));
+    let ret = ret.map(|stmts| stmts.len());
+    assert_eq!(ret.value, 2);
+}
+
+#[test]
+fn test_synchronize() {
+    let mut a = new_parser_wo_sp("a + (b (c)) d; e");
+    assert_eq!(
+        a.synchronize(&[Token::Semi, Token::LParen]),
+        Some(Span::new(0, 3))
+    );
+    assert_eq!(a.synchronize(&[Token::LParen]), None);
+    a.advance();
+    assert_eq!(
+        a.skip_to_closer(Token::LParen, Token::RParen),
+        Some(Span::new(5, 11))
+    );
+    assert_eq!(a.peek().unwrap().tok, Token::Ident);
+    assert_eq!(a.synchronize(&[Token::RParen]), Some(Span::new(12, 16)));
+    assert!(a.eof());
+
+    let mut a = new_parser_wo_sp("(a");
+    a.advance();
+    assert_eq!(
+        a.skip_to_closer(Token::LParen, Token::RParen),
+        Some(Span::new(1, 2))
+    );
+    assert!(a.eof());
+}
+
 #[test]
 fn test_span() {
     let a = new_parser("a+");
     assert_eq!(a.span(), None);
 }
 
+#[test]
+fn test_point() {
+    let mut a = new_parser_wo_sp("a +");
+    assert_eq!(a.point(), Span::point(0));
+    a.advance();
+    assert_eq!(a.point(), Span::point(1));
+    a.advance();
+    assert_eq!(a.point(), Span::point(3));
+    assert_eq!(a.eof_span(), Span::point(3));
+
+    let a = new_parser_wo_sp(" a ");
+    assert_eq!(a.point(), Span::point(1));
+    assert_eq!(a.eof_span(), Span::point(3));
+
+    let a = new_parser("");
+    assert_eq!(a.point(), Span::point(0));
+    assert_eq!(a.eof_span(), Span::point(0));
+}
+
 #[test]
 fn test_chars() {
     let a = new_parser("+ ab");
@@ -376,6 +1368,67 @@ fn test_parse_n() {
     assert!(res.is_err());
 }
 
+#[test]
+fn test_parse_n_empty() {
+    let mut a = new_parser_wo_sp("a +");
+    let res = a.parse_n(|p| Ok(p.advance_cmp(Token::Plus))).unwrap();
+    assert!(!res.data);
+    assert_eq!(res.span, Span::point(0));
+
+    a.advance();
+    let res = a.parse_n(|p| Ok(p.advance_cmp(Token::Ident))).unwrap();
+    assert!(!res.data);
+    assert_eq!(res.span, Span::point(1));
+
+    a.advance();
+    let res = a.parse_n(|p| Ok(p.advance_cmp(Token::Ident))).unwrap();
+    assert_eq!(res.span, Span::point(3));
+}
+
+#[test]
+fn test_span_of() {
+    let mut a = new_parser("ab+c");
+    let lhs = a.parse_n(|p| p.expect(Token::Ident)).unwrap();
+    let op = a.expect(Token::Plus).unwrap();
+    let rhs = a.parse_n(|p| p.expect(Token::Ident)).unwrap();
+    assert_eq!(span_of(&(lhs, op, rhs)), Some(Span::new(0, 4)));
+}
+
+#[test]
+fn test_make_node_at() {
+    let mut sm = SourceMap::new();
+    let mut a = Parser::new(new_lexer_in(&mut sm, "a.txt", "ab+c"));
+    let node = a.parse_n(|p| p.expect(Token::Ident)).unwrap();
+
+    // desugar `ab` into a generated node outside of the parsing context
+    let data = ExpnData::new(ExpnKind::Desugaring("ident".into()), node.span);
+    let expn = sm.register_expansion(data);
+    let generated = a.make_node_at(node.span.with_expn(expn), Token::Plus);
+    assert_eq!(generated.id.get(), node.id.get() + 1);
+    assert_eq!(generated.span.expn(), expn);
+    assert_eq!(sm.expansions().source_span(generated.span), node.span);
+    assert_eq!(
+        sm.lookup_source(generated.span).unwrap().to_string(),
+        "a.txt:1:1"
+    );
+}
+
+#[test]
+fn test_parse_n_source_map() {
+    let mut sm = SourceMap::new();
+    sm.add_file("a.txt", "a");
+    let mut a = Parser::new(new_lexer_in(&mut sm, "b.txt", "+ cd"));
+    let file = sm.find("b.txt").unwrap().id();
+
+    a.advance();
+    let res = a.parse_n(|p| p.expect(Token::Whitespace)).unwrap();
+    assert_eq!(res.span, Span::new_in(file, 1, 2));
+    let res = a.parse_n(|p| p.expect(Token::Ident)).unwrap();
+    assert_eq!(res.span, Span::new_in(file, 2, 4));
+    assert_eq!(sm.get_string(res.span).unwrap(), "cd");
+    assert_eq!(sm.lookup(res.span).unwrap().to_string(), "b.txt:1:3");
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////
 // macro tests
 
@@ -0,0 +1,110 @@
+#[allow(dead_code)] // each test crate uses part of the harness
+mod lex_parse;
+
+use lex_parse::lexer::*;
+use lex_parse::parser::*;
+use reacto::diagnostic::{Applicability, Diagnostic};
+use reacto::parse::Parse;
+use reacto::sarif::SarifWriter;
+use reacto::source_map::SourceMap;
+use reacto::span::Span;
+use serde_json::Value;
+
+// the official schema, which `cargo test -- --ignored` checks the writer against
+const SCHEMA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sarif-schema-2.1.0.json");
+
+fn validate(log: &Value) -> Result<(), Vec<String>> {
+    let schema = std::fs::read_to_string(SCHEMA).expect("sarif schema missing");
+    let schema = serde_json::from_str(&schema).unwrap();
+    let schema = jsonschema::JSONSchema::options()
+        .should_validate_formats(true)
+        .compile(&schema)
+        .unwrap();
+    let ret = schema.validate(log);
+    ret.map_err(|errors| {
+        errors
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect()
+    })
+}
+
+fn parse_diagnostic(sm: &mut SourceMap, name: &str, s: &str) -> Diagnostic {
+    let mut a = Parser::new_wo_sp(new_lexer_in(sm, name, s));
+    a.advance();
+    a.sat(Token::Plus).unwrap_err().into()
+}
+
+fn write_log() -> String {
+    let mut sm = SourceMap::new();
+    let d1 = parse_diagnostic(&mut sm, "a.x", "a\"b\"")
+        .with_code("E0001")
+        .with_note("note")
+        .with_replacement(
+            Span::new_in(sm.find("a.x").unwrap().id(), 1, 1),
+            "add an operator",
+            "+",
+            Applicability::MachineApplicable,
+        );
+    let d2 = parse_diagnostic(&mut sm, "my dir/b#1.x", "\"é\" \"é\"")
+        .with_code("E0002")
+        .with_label(Span::new_in(sm.find("a.x").unwrap().id(), 0, 1), "see");
+    let d3 = Diagnostic::warning("no location").with_code("E0001");
+    SarifWriter::new("reacto")
+        .version("0.1.0")
+        .information_uri("https://example.com")
+        .write(vec![&d1, &d2, &d3], &sm)
+}
+
+#[test]
+fn test_sarif() {
+    let log: Value = serde_json::from_str(&write_log()).unwrap();
+
+    let run = &log["runs"][0];
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[1]["id"], "E0002");
+    assert_eq!(run["artifacts"].as_array().unwrap().len(), 2);
+
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[2]["ruleIndex"], 0);
+    assert_eq!(results[2]["level"], "warning");
+    assert!(results[2]["locations"].as_array().unwrap().is_empty());
+
+    let location = &results[1]["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], "my%20dir/b%231.x");
+    let region = &location["region"];
+    assert_eq!(region["startLine"], 1);
+    assert_eq!(region["startColumn"], 5);
+    assert_eq!(region["charOffset"], 4);
+    assert_eq!(region["charLength"], 3);
+    assert_eq!(region["byteOffset"], 5);
+    assert_eq!(region["byteLength"], 4);
+
+    let change = &results[0]["fixes"][0]["artifactChanges"][0];
+    assert_eq!(change["artifactLocation"]["uri"], "a.x");
+    assert_eq!(change["replacements"][0]["insertedContent"]["text"], "+");
+
+    let related = &results[1]["relatedLocations"][0];
+    assert_eq!(related["message"]["text"], "see");
+}
+
+#[test]
+#[ignore = "needs the official schema at tests/sarif-schema-2.1.0.json"]
+fn test_sarif_schema() {
+    let log = serde_json::from_str(&write_log()).unwrap();
+    validate(&log).unwrap();
+
+    let invalid = [
+        r#"{"version":"2.1.0","runs":[{"results":[]}]}"#,
+        r#"{"version":"2.0.0","runs":[]}"#,
+        r#"{"version":"2.1.0","runs":[{"tool":{"driver":{"name":"t"}},"artifacts":[{"location":{"uri":"a b#.x"}}]}]}"#,
+    ];
+    for log in invalid {
+        assert!(
+            validate(&serde_json::from_str(log).unwrap()).is_err(),
+            "{}",
+            log
+        );
+    }
+}
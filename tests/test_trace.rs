@@ -0,0 +1,148 @@
+#![cfg(feature = "trace")]
+
+#[allow(dead_code)] // each test crate uses part of the harness
+mod lex_parse;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use lex_parse::lexer::*;
+use lex_parse::parser::*;
+use reacto::parse::Parse;
+use reacto::trace::{Event, Outcome};
+
+// list := item (',' item)*, with `;` as the separator
+// item := ident '+' ident | ident
+fn parse_item(p: &mut Parser) -> Result<(), ParseError> {
+    p.parse_rule("item", |p: &mut Parser| {
+        let sum = p.parse_rule("sum", |p: &mut Parser| {
+            p.expect(Token::Ident)?;
+            p.expect(Token::Plus)?;
+            p.expect(Token::Ident)
+        });
+        if sum.is_err() {
+            p.expect(Token::Ident)?;
+        }
+        Ok(())
+    })
+}
+
+fn parse_list(p: &mut Parser) -> Result<(), ParseError> {
+    p.parse_rule("list", |p: &mut Parser| {
+        parse_item(p)?;
+        while p.advance_cmp(Token::Semi) {
+            parse_item(p)?;
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_trace() {
+    let a = new_parser_wo_sp("a + b ; c");
+    assert!(a.ctx().trace_log().is_none());
+
+    let mut a = new_parser_wo_sp("a + b ; c").trace();
+    parse_list(&mut a).unwrap();
+    let trace = a.ctx().trace_log().unwrap();
+    assert_eq!(
+        trace.render_tree(),
+        "\
+list @0
+  item @0
+    sum @0
+      => ok @3
+    => ok @3
+  item @4
+    sum @4
+      <- @5 to @4
+      => fail @4
+    => ok @5
+  => ok @5
+"
+    );
+    assert_eq!(
+        trace.render_flat().lines().collect::<Vec<_>>(),
+        vec![
+            "enter list @0",
+            "enter item @0",
+            "enter sum @0",
+            "ok sum @3",
+            "ok item @3",
+            "enter item @4",
+            "enter sum @4",
+            "roll back @5 to @4",
+            "fail sum @4",
+            "ok item @5",
+            "ok list @5",
+        ]
+    );
+}
+
+#[test]
+fn test_trace_failure() {
+    let mut a = new_parser_wo_sp("a ; +").trace();
+    assert!(parse_list(&mut a).is_err());
+    let events: Vec<_> = a.ctx().trace_log().unwrap().events().copied().collect();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|e| matches!(e, Event::Enter { .. }))
+            .count(),
+        5
+    );
+    assert_eq!(
+        events[events.len() - 2..],
+        [
+            Event::RollBack { from: 2, to: 0 },
+            Event::Exit {
+                rule: "list",
+                cursor: 0,
+                outcome: Outcome::Failure
+            },
+        ]
+    );
+
+    // unnamed rules show up as `parse`
+    let mut a = new_parser_wo_sp("a").trace();
+    a.parse(|p: &mut Parser| p.parse_ident()).unwrap();
+    a.ctx_mut().trace_log_mut().unwrap().clear();
+    a.parse(|p: &mut Parser| p.parse_ident()).unwrap_err();
+    assert_eq!(
+        a.ctx().trace_log().unwrap().render_flat(),
+        "enter parse @1\nfail parse @1\n"
+    );
+}
+
+#[test]
+fn test_trace_stopped() {
+    // every rule which is entered exits, also when the parse is cancelled
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut a = new_parser_wo_sp("a + b ; c")
+        .trace()
+        .map_ctx(|c| c.cancel_on(flag.clone()));
+    let ret = a.parse_rule("outer", |p: &mut Parser| {
+        parse_item(p)?;
+        flag.store(true, Ordering::Relaxed);
+        parse_list(p)
+    });
+    assert!(ret.is_err());
+    let events: Vec<_> = a.ctx().trace_log().unwrap().events().copied().collect();
+    let enters = events
+        .iter()
+        .filter(|e| matches!(e, Event::Enter { .. }))
+        .count();
+    let exits = events
+        .iter()
+        .filter(|e| matches!(e, Event::Exit { .. }))
+        .count();
+    assert_eq!(enters, exits);
+    assert_eq!(
+        events.last(),
+        Some(&Event::Exit {
+            rule: "outer",
+            cursor: 0,
+            outcome: Outcome::Failure
+        })
+    );
+}